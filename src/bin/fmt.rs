@@ -0,0 +1,103 @@
+#![feature(exit_status)]
+
+extern crate lisp;
+
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write, self};
+
+use lisp::diagnostics;
+use lisp::syntax::codemap::SourceMap;
+use lisp::syntax::{parse, pp};
+use lisp::util::interner::Interner;
+
+const USAGE: &'static str = "usage: fmt [--check] [--width <columns>] <file>...\n";
+// NB used when `--width` is not given
+const WIDTH: usize = 80;
+
+/// Formats the file at `path` in place, or, in `check` mode, only checks that it's formatted.
+/// Returns `false` if the file has syntax errors or, in `check` mode, if it's not formatted
+fn fmt(path: &str, width: usize, check: bool) -> io::Result<bool> {
+    let mut text = String::new();
+    try!(File::open(path).and_then(|mut file| file.read_to_string(&mut text)));
+
+    let mut map = SourceMap::new();
+    let ref mut interner = Interner::new();
+    let file = map.add(String::from(path), text);
+    let source = map.source(file);
+
+    let nodes = parse::cst(source);
+
+    // NB the strict parser decides whether the file is broken, the recovering parser is only used
+    // to report every syntax error rather than just the first one
+    if let Err(error) = parse::lower(&nodes, source, interner) {
+        let (_, mut errors) = parse::recover(source, interner);
+
+        if errors.is_empty() {
+            errors.push(error);
+        }
+
+        let stderr = io::stderr();
+        let mut stderr = stderr.lock();
+
+        for error in errors {
+            try!(stderr.write_all(diagnostics::syntax(error, &map).as_bytes()));
+        }
+
+        return Ok(false)
+    }
+
+    let formatted = pp::nodes(&nodes, source, width);
+
+    if formatted == source.as_str() {
+        Ok(true)
+    } else if check {
+        println!("{}: not formatted", path);
+
+        Ok(false)
+    } else {
+        try!(File::create(path).and_then(|mut file| file.write_all(formatted.as_bytes())));
+
+        Ok(true)
+    }
+}
+
+fn usage() {
+    env::set_exit_status(1);
+    io::stderr().write_all(USAGE.as_bytes()).ok();
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut check = false;
+    let mut paths = vec![];
+    let mut width = WIDTH;
+
+    while let Some(arg) = args.next() {
+        if arg == "--check" {
+            check = true;
+        } else if arg == "--width" {
+            match args.next().and_then(|width| width.parse().ok()) {
+                None => return usage(),
+                Some(columns) => width = columns,
+            }
+        } else {
+            paths.push(arg);
+        }
+    }
+
+    if paths.is_empty() {
+        return usage()
+    }
+
+    for path in &paths {
+        match fmt(path, width, check) {
+            Err(e) => {
+                env::set_exit_status(1);
+                io::stderr().write_fmt(format_args!("error: {}: {}\n", path, e)).ok();
+            },
+            Ok(false) => env::set_exit_status(1),
+            Ok(true) => {},
+        }
+    }
+}
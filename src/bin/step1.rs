@@ -8,46 +8,65 @@ use std::io::{StdoutLock, Write, self};
 
 use lines::Lines;
 use lisp::diagnostics;
+use lisp::repl::Input;
 use lisp::syntax::ast::Expr;
-use lisp::syntax::codemap::Source;
+use lisp::syntax::codemap::{Source, SourceMap};
 use lisp::syntax::pp;
 use lisp::syntax::{Error, parse};
 use lisp::util::interner::Interner;
 
-fn read(source: &Source, interner: &mut Interner) -> Result<Expr, Error> {
-    parse::expr(source, interner)
+fn read(source: Source, interner: &mut Interner) -> Result<Vec<Expr>, Error> {
+    parse::exprs(source, interner)
 }
 
 fn eval(input: Expr) -> Expr {
     input
 }
 
-fn print(output: &Expr, source: &Source, stdout: &mut StdoutLock) -> io::Result<()> {
-    let mut string = pp::expr(output, source);
+fn print(
+    output: &Expr,
+    source: Source,
+    interner: &Interner,
+    stdout: &mut StdoutLock,
+) -> io::Result<()> {
+    let mut string = pp::expr(output, source, interner);
     string.push('\n');
     stdout.write_all(string.as_bytes())
 }
 
 fn rep(stdout: &mut StdoutLock) -> io::Result<()> {
     const PROMPT: &'static str = "> ";
+    // NB shown while the current form is incomplete
+    const CONTINUATION_PROMPT: &'static str = "| ";
 
     let stdin = io::stdin();
     let mut lines = Lines::from(stdin.lock());
 
+    let mut input = Input::new();
+    let mut map = SourceMap::new();
     let ref mut interner = Interner::new();
 
     try!(stdout.write_all(PROMPT.as_bytes()));
     try!(stdout.flush());
     while let Some(line) = lines.next() {
-        let source = Source::new(try!(line));
-
-        if !source.as_str().trim().is_empty() {
-            match read(source, interner) {
-                Err(error) => {
-                    try!(stdout.write_all(diagnostics::syntax(error, source).as_bytes()))
-                },
-                Ok(expr) => try!(print(&eval(expr), source, stdout)),
-            }
+        let text = match input.push_line(try!(line)) {
+            // NB the form continues in the next line, keep reading
+            None => {
+                try!(stdout.write_all(CONTINUATION_PROMPT.as_bytes()));
+                try!(stdout.flush());
+                continue
+            },
+            Some(text) => text,
+        };
+
+        let file = map.add(String::from("<repl>"), text);
+        let source = map.source(file);
+
+        match read(source, interner) {
+            Err(error) => try!(stdout.write_all(diagnostics::syntax(error, &map).as_bytes())),
+            Ok(exprs) => for expr in exprs {
+                try!(print(&eval(expr), source, interner, stdout))
+            },
         }
 
         try!(stdout.write_all(PROMPT.as_bytes()));
@@ -7,19 +7,25 @@ use std::io::{StdoutLock, Write, self};
 
 use lines::Lines;
 use lisp::diagnostics;
-use lisp::eval::env::{Stack, self};
+use lisp::repl::Input;
+use lisp::eval::env::{Env, self};
 use lisp::eval::{Value, self};
 use lisp::syntax::ast::Expr;
-use lisp::syntax::codemap::Source;
+use lisp::syntax::codemap::{Source, SourceMap};
 use lisp::syntax::{parse, self};
 use lisp::util::interner::Interner;
 
-fn read(source: &Source, interner: &mut Interner) -> Result<Expr, syntax::Error> {
-    parse::expr(source, interner)
+fn read(source: Source, interner: &mut Interner) -> Result<Vec<Expr>, syntax::Error> {
+    parse::exprs(source, interner)
 }
 
-fn eval(input: &Expr, source: &Source, env: &mut Stack) -> Result<Value, eval::Error> {
-    eval::expr(input, source, env)
+fn eval(
+    input: &Expr,
+    env: &Env,
+    map: &mut SourceMap,
+    interner: &mut Interner,
+) -> Result<Value, eval::Error> {
+    eval::expr(input, env, map, interner)
 }
 
 fn print(value: &Value, interner: &Interner, stdout: &mut StdoutLock) -> io::Result<()> {
@@ -28,30 +34,47 @@ fn print(value: &Value, interner: &Interner, stdout: &mut StdoutLock) -> io::Res
 
 fn rep(stdout: &mut StdoutLock) -> io::Result<()> {
     const PROMPT: &'static str = "> ";
+    // NB shown while the current form is incomplete
+    const CONTINUATION_PROMPT: &'static str = "| ";
 
     let stdin = io::stdin();
     let mut lines = Lines::from(stdin.lock());
 
+    let mut input = Input::new();
+    let mut map = SourceMap::new();
     let ref mut interner = Interner::new();
-    let ref mut env = env::default(interner);
+    let ref env = env::default(interner);
 
     try!(stdout.write_all(PROMPT.as_bytes()));
     try!(stdout.flush());
     while let Some(line) = lines.next() {
-        let source = Source::new(try!(line));
-
-        if !source.as_str().trim().is_empty() {
-            match read(source, interner) {
-                Err(error) => {
-                    try!(stdout.write_all(diagnostics::syntax(error, source).as_bytes()))
-                },
-                Ok(expr) => match eval(&expr, source, env) {
+        let text = match input.push_line(try!(line)) {
+            // NB the form continues in the next line, keep reading
+            None => {
+                try!(stdout.write_all(CONTINUATION_PROMPT.as_bytes()));
+                try!(stdout.flush());
+                continue
+            },
+            Some(text) => text,
+        };
+
+        let file = map.add(String::from("<repl>"), text);
+        let exprs = read(map.source(file), interner);
+
+        match exprs {
+            Err(error) => try!(stdout.write_all(diagnostics::syntax(error, &map).as_bytes())),
+            Ok(exprs) => for expr in exprs {
+                match eval(&expr, env, &mut map, interner) {
                     Err(error) => {
-                        try!(stdout.write_all(diagnostics::eval(error, source).as_bytes()))
+                        let diagnostic = diagnostics::eval(error, &map, interner);
+
+                        try!(stdout.write_all(diagnostic.as_bytes()));
+
+                        break
                     },
                     Ok(value) => try!(print(&value, interner, stdout)),
-                },
-            }
+                }
+            },
         }
 
         try!(stdout.write_all(PROMPT.as_bytes()));
@@ -3,71 +3,118 @@
 use unicode_width::UnicodeWidthStr;
 
 use eval;
-use syntax::codemap::{Source, Spanned};
+use syntax::codemap::{File, SourceMap, Span, Spanned};
 use syntax;
+use util::interner::Interner;
 
 /// Diagnose evaluation error
-pub fn eval(error: eval::Error, source: &Source) -> String {
-    common(error, source, |error, string| {
-        use eval::Error_::*;
+///
+/// The error is reported against the file of the source map that contains its span
+pub fn eval(error: eval::Error, map: &SourceMap, interner: &Interner) -> String {
+    let file = map.get(error.span.file);
 
+    let (error, in_expansion) = match error.node {
+        eval::Error_::MacroExpansion(node) => (Spanned::new(error.span, *node), true),
+        node => (Spanned::new(error.span, node), false),
+    };
+
+    let mut string = common(error, file, |error, string| {
         match error {
-            EmptyList => string.push_str("empty list"),
-            ExpectedFunction => string.push_str("expected function"),
-            ExpectedSymbol => string.push_str("expected symbol"),
-            UndefinedSymbol => string.push_str("undefined symbol"),
-            UnsupportedOperation => string.push_str("unsupported operation"),
+            // NB unwrapped above
+            eval::Error_::MacroExpansion(_) => unreachable!(),
+            eval::Error_::Throw(value) => {
+                string.push_str("uncaught exception: ");
+                string.push_str(&value.display(interner));
+            },
+            error => string.push_str(&error.to_string()),
         }
-    })
+    });
+
+    if in_expansion {
+        string.push_str("note: this error originates in the expansion of this macro call\n");
+    }
+
+    string
 }
 
 /// Diagnose syntax error
-pub fn syntax(error: syntax::Error, source: &Source) -> String {
-    common(error, source, |error, string| {
-        use syntax::Error_::*;
+///
+/// The error is reported against the file of the source map that contains its span
+pub fn syntax(error: syntax::Error, map: &SourceMap) -> String {
+    let file = map.get(error.span.file);
 
-        match error {
-            EmptyKeyword => string.push_str("empty keyword"),
-            ExpectedEndOfLine => string.push_str("expected end of line"),
-            IncorrectCloseDelimiter => string.push_str("incorrect close delimiter"),
-            IntegerTooLarge => string.push_str("integer literal is too large"),
-            OperatorNotAllowedHere => string.push_str("keyword not allowed here"),
-            UnclosedDelimiter => string.push_str("un-closed delimiter"),
-            UnknownCharacterEscape => string.push_str("unknown character escape"),
-            UnknownStartOfToken => string.push_str("unknown start of token"),
-            UnterminatedString => string.push_str("unterminated string literal"),
-        };
-    })
+    common(error, file, |error, string| string.push_str(error.description()))
 }
 
 /// Common diagnostic routine
-fn common<E, F>(error: Spanned<E>, source: &Source, f: F) -> String where
+///
+/// If the file that contains the error is unknown, only the error message is printed
+fn common<E, F>(error: Spanned<E>, file: Option<&File>, f: F) -> String where
     F: FnOnce(E, &mut String),
 {
-    // Check that this is not the dummy span
-    debug_assert!(error.span.lo != 0 || error.span.hi != 0);
-
     let mut string = String::from_str("error: ");
 
     f(error.node, &mut string);
 
-    string.push('\n');
-    string.push_str(source.as_str());
     string.push('\n');
 
-    for _ in 0..UnicodeWidthStr::width(&source.as_str()[..error.span.lo]) {
-        string.push(' ');
+    if let Some(file) = file {
+        snippet(error.span, file, &mut string);
     }
 
-    string.push('^');
+    string
+}
 
-    if error.span.hi <= source.as_str().len() {
-        for _ in 1..UnicodeWidthStr::width(&source[error.span]) {
-            string.push('~');
+/// Prints the `file:line:col` location of `span`, and the line of source code that contains the
+/// start of the span, with a line number gutter, then underlines the span
+///
+/// ``` text
+///  --> tests/mal/error.mal:4:2
+///   |
+/// 4 | (undefined-function x)
+///   |  ^~~~~~~~~~~~~~~~~~
+/// ```
+fn snippet(span: Span, file: &File, string: &mut String) {
+    // Check that this is not the dummy span
+    debug_assert!(!span.is_dummy());
+
+    let lo = file.location(span.lo);
+    let hi = file.location(span.hi);
+    let line = file.line(lo.line);
+    let gutter = lo.line.to_string();
+    let margin = gutter.chars().map(|_| ' ').collect::<String>();
+
+    string.push_str(&format!("{}--> {}:{}:{}\n", margin, file.name(), lo.line, lo.col));
+    string.push_str(&format!("{} |\n", margin));
+    string.push_str(&format!("{} | {}\n", gutter, line));
+    string.push_str(&format!("{} | ", margin));
+
+    let before = line.chars().take(lo.col - 1).collect::<String>();
+
+    // NB tabs are copied rather than replaced with spaces, so the underline lines up with the
+    // span, whatever the width of a tab is
+    for (i, c) in before.char_indices() {
+        if c == '\t' {
+            string.push('\t');
+        } else {
+            for _ in 0..UnicodeWidthStr::width(&before[i..i + c.len_utf8()]) {
+                string.push(' ');
+            }
         }
     }
 
-    string.push('\n');
+    // NB a span that spans several lines is underlined until the end of its first line
+    let underlined = if hi.line == lo.line {
+        &file.as_str()[span.lo..span.hi]
+    } else {
+        &line[before.len()..]
+    };
 
-    string
+    string.push('^');
+
+    for _ in 1..UnicodeWidthStr::width(underlined) {
+        string.push('~');
+    }
+
+    string.push('\n');
 }
@@ -1,45 +1,89 @@
 //! Environment
 
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fs::File;
+use std::i64;
+use std::io::Read;
+use std::slice::Chunks;
 
-use eval::{Function, Value};
+use rc::Rc;
+
+use eval::{Arity, Atom, Error_, Failure, Function, Type, Value};
+use syntax::codemap::{Source, SourceMap, Span};
+use syntax::parse;
+use util::bigint::BigInt;
 use util::interner::{Interner, Name};
 
-/// A stack of environments
-pub struct Stack<'a> {
-    top: Env,
-    bottom: Option<&'a Stack<'a>>,
+/// The size, in bits, of the largest integer power that `pow` computes. Larger powers are a domain
+/// error, as they could take any amount of memory and time to compute
+const MAX_POWER_BITS: u64 = 1 << 17;
+
+/// An environment: a mutable frame of bindings that may have a parent environment
+///
+/// Environments are reference counted, cloning an environment doesn't copy its bindings, instead
+/// the clone refers to the same frame. This lets closures capture the environment they were
+/// created in, and lets an embedding host keep an environment around between evaluations.
+#[derive(Clone)]
+pub struct Env(Rc<RefCell<Frame>>);
+
+struct Frame {
+    bindings: HashMap<Name, Value>,
+    parent: Option<Env>,
 }
 
-impl<'a> Stack<'a> {
-    /// Pushes a new environment into the stack
-    pub fn push(&self, env: Env) -> Stack {
-        Stack {
-            top: env,
-            bottom: Some(self),
-        }
+impl Env {
+    /// Creates an empty environment with no parent
+    pub fn new() -> Env {
+        Env(Rc::new(RefCell::new(Frame {
+            bindings: HashMap::new(),
+            parent: None,
+        })))
     }
 
-    /// Searches the stack (from top to bottom) and retrieves the first value that's associated to
-    /// `symbol`
-    pub fn get(&self, symbol: &Name) -> Option<&Value> {
-        self.top.get(symbol).or_else(|| {
-            self.bottom.as_ref().and_then(|stack| stack.get(symbol))
-        })
+    /// Creates an empty environment whose parent is this environment
+    pub fn push(&self) -> Env {
+        Env(Rc::new(RefCell::new(Frame {
+            bindings: HashMap::new(),
+            parent: Some(self.clone()),
+        })))
     }
 
-    /// Inserts a `symbol`/`value` pair in the top environment
-    pub fn insert(&mut self, symbol: Name, value: Value) {
-        self.top.insert(symbol, value);
+    /// Returns the outermost ancestor of this environment, i.e. the global environment
+    pub fn root(&self) -> Env {
+        let frame = self.0.borrow();
+
+        let root = match frame.parent {
+            None => self.clone(),
+            Some(ref parent) => parent.root(),
+        };
+
+        root
     }
-}
 
-/// Environment
-pub type Env = HashMap<Name, Value>;
+    /// Searches the environment and then its ancestors, and retrieves the first value that's
+    /// associated to `symbol`
+    pub fn get(&self, symbol: &Name) -> Option<Value> {
+        let frame = self.0.borrow();
 
-/// The default environment stack
-pub fn default(interner: &mut Interner) -> Stack<'static> {
-    let mut env = Env::new();
+        let value = match frame.bindings.get(symbol) {
+            Some(value) => Some(value.clone()),
+            None => frame.parent.as_ref().and_then(|parent| parent.get(symbol)),
+        };
+
+        value
+    }
+
+    /// Inserts a `symbol`/`value` pair in this environment
+    pub fn insert(&self, symbol: Name, value: Value) {
+        self.0.borrow_mut().bindings.insert(symbol, value);
+    }
+}
+
+/// The default (global) environment
+pub fn default(interner: &mut Interner) -> Env {
+    let env = Env::new();
 
     env.insert(interner.intern("*"), Value::Function(Function::new(mul)));
     env.insert(interner.intern("+"), Value::Function(Function::new(add)));
@@ -47,67 +91,751 @@ pub fn default(interner: &mut Interner) -> Stack<'static> {
     env.insert(interner.intern("/"), Value::Function(Function::new(div)));
     env.insert(interner.intern("<"), Value::Function(Function::new(lt)));
     env.insert(interner.intern("<="), Value::Function(Function::new(le)));
+    env.insert(interner.intern("="), Value::Function(Function::new(eq)));
     env.insert(interner.intern(">"), Value::Function(Function::new(gt)));
     env.insert(interner.intern(">="), Value::Function(Function::new(ge)));
+    env.insert(interner.intern("abs"), Value::Function(Function::new(abs)));
+    env.insert(interner.intern("acos"), Value::Function(Function::new(acos)));
+    env.insert(interner.intern("asin"), Value::Function(Function::new(asin)));
+    env.insert(interner.intern("assoc"), Value::Function(Function::new(assoc)));
+    env.insert(interner.intern("atan"), Value::Function(Function::new(atan)));
+    env.insert(interner.intern("atom"), Value::Function(Function::new(atom)));
+    env.insert(interner.intern("atom?"), Value::Function(Function::new(is_atom)));
+    env.insert(interner.intern("ceil"), Value::Function(Function::new(ceil)));
+    env.insert(interner.intern("concat"), Value::Function(Function::new(concat)));
+    env.insert(interner.intern("conj"), Value::Function(Function::new(conj)));
+    env.insert(interner.intern("cons"), Value::Function(Function::new(cons)));
+    env.insert(interner.intern("contains?"), Value::Function(Function::new(contains)));
+    env.insert(interner.intern("cos"), Value::Function(Function::new(cos)));
+    env.insert(interner.intern("count"), Value::Function(Function::new(count)));
+    env.insert(interner.intern("deref"), Value::Function(Function::new(deref)));
+    env.insert(interner.intern("dissoc"), Value::Function(Function::new(dissoc)));
+    env.insert(interner.intern("empty?"), Value::Function(Function::new(is_empty)));
+    env.insert(interner.intern("eval"), Value::Function(Function::with_interpreter(eval)));
+    env.insert(interner.intern("first"), Value::Function(Function::new(first)));
+    env.insert(interner.intern("floor"), Value::Function(Function::new(floor)));
+    env.insert(interner.intern("get"), Value::Function(Function::new(get)));
+    env.insert(interner.intern("hash-map"), Value::Function(Function::new(hash_map)));
+    env.insert(interner.intern("keys"), Value::Function(Function::with_interpreter(keys)));
+    env.insert(interner.intern("list"), Value::Function(Function::new(list)));
+    env.insert(interner.intern("list?"), Value::Function(Function::new(is_list)));
+    env.insert(
+        interner.intern("load-file"),
+        Value::Function(Function::with_interpreter(load_file)),
+    );
+    env.insert(interner.intern("nth"), Value::Function(Function::new(nth)));
+    env.insert(interner.intern("pow"), Value::Function(Function::new(pow)));
+    env.insert(
+        interner.intern("read-string"),
+        Value::Function(Function::with_interpreter(read_string)),
+    );
+    env.insert(interner.intern("reset!"), Value::Function(Function::new(reset)));
+    env.insert(interner.intern("rest"), Value::Function(Function::new(rest)));
+    env.insert(interner.intern("round"), Value::Function(Function::new(round)));
+    env.insert(interner.intern("sin"), Value::Function(Function::new(sin)));
+    env.insert(interner.intern("sqrt"), Value::Function(Function::new(sqrt)));
+    env.insert(interner.intern("swap!"), Value::Function(Function::with_interpreter(swap)));
+    env.insert(interner.intern("tan"), Value::Function(Function::new(tan)));
+    env.insert(interner.intern("throw"), Value::Function(Function::new(throw)));
+    env.insert(interner.intern("vals"), Value::Function(Function::with_interpreter(vals)));
+
+    env
+}
+
+/// A numeric argument
+///
+/// Like in `Value`, big integers are only used for integers that don't fit in an `i64`
+#[derive(Clone)]
+enum Number {
+    BigInt(BigInt),
+    Float(f64),
+    Integer(i64),
+}
+
+impl Number {
+    /// Converts a big integer into a number, demoting it to an `i64` if it fits
+    fn from_bigint(integer: BigInt) -> Number {
+        match integer.to_i64() {
+            None => Number::BigInt(integer),
+            Some(integer) => Number::Integer(integer),
+        }
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Number::BigInt(integer) => Value::BigInt(integer),
+            Number::Float(float) => Value::Float(float),
+            Number::Integer(integer) => Value::Integer(integer),
+        }
+    }
+
+    /// Converts the number into a big integer, returns `None` if it's a float
+    fn to_bigint(&self) -> Option<BigInt> {
+        match *self {
+            Number::BigInt(ref integer) => Some(integer.clone()),
+            Number::Float(_) => None,
+            Number::Integer(integer) => Some(BigInt::from_i64(integer)),
+        }
+    }
+
+    /// Converts the number into a float, large integers may lose precision
+    fn to_float(&self) -> f64 {
+        match *self {
+            Number::BigInt(ref integer) => integer.to_f64(),
+            Number::Float(float) => float,
+            Number::Integer(integer) => integer as f64,
+        }
+    }
+}
+
+fn abs(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(1)));
+
+    let number = match try!(as_number(args, 0)) {
+        Number::BigInt(integer) => Number::BigInt(integer.abs()),
+        Number::Float(float) => Number::Float(float.abs()),
+        // NB `i64::MIN` has no positive counterpart
+        Number::Integer(integer) if integer == i64::MIN => {
+            Number::BigInt(BigInt::from_i64(integer).abs())
+        },
+        Number::Integer(integer) => Number::Integer(integer.abs()),
+    };
+
+    Ok(number.into_value())
+}
+
+fn acos(args: &[Value]) -> Result<Value, Failure> {
+    unary(args, |x| x.acos())
+}
+
+fn add(args: &[Value]) -> Result<Value, Failure> {
+    fold(args, 0, Number::Integer(0), |a, b| a.checked_add(b), |a, b| a + b, |a, b| a + b)
+}
+
+/// Checks that the number of `args` matches the `arity` of the function
+fn arity(args: &[Value], arity: Arity) -> Result<(), Failure> {
+    if arity.accepts(args.len()) {
+        Ok(())
+    } else {
+        Err(Failure::new(None, Error_::ArityMismatch(arity, args.len())))
+    }
+}
+
+/// Views the argument at `index` as an atom
+fn as_atom(args: &[Value], index: usize) -> Result<&Atom, Failure> {
+    match args[index] {
+        Value::Atom(ref atom) => Ok(atom),
+        ref value => Err(mismatch(index, Type::Atom, value)),
+    }
+}
+
+/// Views the argument at `index` as an integer
+fn as_integer(args: &[Value], index: usize) -> Result<i64, Failure> {
+    match args[index] {
+        Value::Integer(integer) => Ok(integer),
+        ref value => Err(mismatch(index, Type::Integer, value)),
+    }
+}
+
+/// Views the argument at `index` as a map
+fn as_map(args: &[Value], index: usize) -> Result<&HashMap<Value, Value>, Failure> {
+    match args[index] {
+        Value::Map(ref map) => Ok(map),
+        ref value => Err(mismatch(index, Type::Map, value)),
+    }
+}
+
+/// Views the argument at `index` as a number
+fn as_number(args: &[Value], index: usize) -> Result<Number, Failure> {
+    match args[index] {
+        Value::BigInt(ref integer) => Ok(Number::BigInt(integer.clone())),
+        Value::Float(float) => Ok(Number::Float(float)),
+        Value::Integer(integer) => Ok(Number::Integer(integer)),
+        ref value => Err(mismatch(index, Type::Number, value)),
+    }
+}
+
+/// Views the argument at `index` as a string
+fn as_string(args: &[Value], index: usize) -> Result<&str, Failure> {
+    match args[index] {
+        Value::String(ref string) => Ok(string),
+        ref value => Err(mismatch(index, Type::String, value)),
+    }
+}
+
+/// Views the argument at `index`, a list, a vector or `nil` (the empty sequence), as a slice of
+/// values
+fn as_seq(args: &[Value], index: usize) -> Result<&[Value], Failure> {
+    match args[index] {
+        Value::List(ref elems, _) | Value::Vector(ref elems, _) => Ok(elems),
+        Value::Nil => Ok(&[][..]),
+        ref value => Err(mismatch(index, Type::Sequence, value)),
+    }
+}
+
+fn asin(args: &[Value]) -> Result<Value, Failure> {
+    unary(args, |x| x.asin())
+}
+
+fn assoc(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::AtLeast(1)));
+
+    let mut map = try!(as_map(args, 0)).clone();
+
+    for pair in try!(pairs(args, 1)) {
+        map.insert(pair[0].clone(), pair[1].clone());
+    }
+
+    Ok(Value::Map(map))
+}
+
+fn atan(args: &[Value]) -> Result<Value, Failure> {
+    unary(args, |x| x.atan())
+}
+
+fn atom(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(1)));
+
+    Ok(Value::Atom(Atom::new(args[0].clone())))
+}
+
+fn ceil(args: &[Value]) -> Result<Value, Failure> {
+    rounding(args, |x| x.ceil())
+}
+
+/// Combines two numbers with an arithmetic operation. Integers are combined with the checked
+/// operation `integer`, and if that overflows, with `bigint`. If either operand is a float, both
+/// are combined as floats with `float`
+fn combine<F, G, H>(a: Number, b: Number, integer: &F, bigint: &G, float: &H) -> Number where
+    F: Fn(i64, i64) -> Option<i64>,
+    G: Fn(&BigInt, &BigInt) -> BigInt,
+    H: Fn(f64, f64) -> f64,
+{
+    match (a, b) {
+        (Number::BigInt(a), Number::BigInt(b)) => Number::from_bigint(bigint(&a, &b)),
+        (Number::BigInt(a), Number::Integer(b)) => {
+            Number::from_bigint(bigint(&a, &BigInt::from_i64(b)))
+        },
+        (Number::Integer(a), Number::BigInt(b)) => {
+            Number::from_bigint(bigint(&BigInt::from_i64(a), &b))
+        },
+        (Number::Integer(a), Number::Integer(b)) => match integer(a, b) {
+            None => Number::from_bigint(bigint(&BigInt::from_i64(a), &BigInt::from_i64(b))),
+            Some(integer) => Number::Integer(integer),
+        },
+        (a, b) => Number::Float(float(a.to_float(), b.to_float())),
+    }
+}
+
+/// Compares two numbers, floats are compared as per IEEE 754 so `NaN` is not comparable
+fn compare(a: &Number, b: &Number) -> Option<Ordering> {
+    match (a, b) {
+        (&Number::BigInt(ref a), &Number::BigInt(ref b)) => Some(a.cmp(b)),
+        (&Number::BigInt(ref a), &Number::Integer(b)) => Some(a.cmp(&BigInt::from_i64(b))),
+        (&Number::Integer(a), &Number::BigInt(ref b)) => Some(BigInt::from_i64(a).cmp(b)),
+        (&Number::Integer(a), &Number::Integer(b)) => Some(a.cmp(&b)),
+        (a, b) => a.to_float().partial_cmp(&b.to_float()),
+    }
+}
+
+fn concat(args: &[Value]) -> Result<Value, Failure> {
+    let mut elems = vec![];
+
+    for index in 0..args.len() {
+        elems.extend(try!(as_seq(args, index)).iter().cloned());
+    }
+
+    Ok(Value::List(elems, Span::dummy()))
+}
+
+fn conj(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::AtLeast(1)));
+
+    let elems = &args[1..];
+
+    match args[0] {
+        Value::List(..) | Value::Nil => {
+            // NB each element is added to the front, so they end up in reverse order
+            let mut list = elems.iter().rev().cloned().collect::<Vec<_>>();
+
+            list.extend(try!(as_seq(args, 0)).iter().cloned());
+
+            Ok(Value::List(list, Span::dummy()))
+        },
+        Value::Vector(ref vector, _) => {
+            let mut vector = vector.clone();
+
+            vector.extend(elems.iter().cloned());
+
+            Ok(Value::Vector(vector, Span::dummy()))
+        },
+        ref value => Err(mismatch(0, Type::Sequence, value)),
+    }
+}
+
+fn cons(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(2)));
+
+    let tail = try!(as_seq(args, 1));
+    let mut list = Vec::with_capacity(tail.len() + 1);
+
+    list.push(args[0].clone());
+    list.extend(tail.iter().cloned());
+
+    Ok(Value::List(list, Span::dummy()))
+}
+
+fn contains(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(2)));
+
+    match args[0] {
+        Value::Nil => Ok(Value::Bool(false)),
+        _ => Ok(Value::Bool(try!(as_map(args, 0)).contains_key(&args[1]))),
+    }
+}
+
+fn cos(args: &[Value]) -> Result<Value, Failure> {
+    unary(args, |x| x.cos())
+}
+
+fn count(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(1)));
+
+    Ok(Value::Integer(try!(as_seq(args, 0)).len() as i64))
+}
+
+fn deref(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(1)));
+
+    Ok(try!(as_atom(args, 0)).get())
+}
+
+fn dissoc(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::AtLeast(1)));
+
+    let mut map = try!(as_map(args, 0)).clone();
+
+    for key in &args[1..] {
+        map.remove(key);
+    }
+
+    Ok(Value::Map(map))
+}
+
+fn div(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::AtLeast(1)));
 
-    Stack {
-        top: env,
-        bottom: None,
+    // NB `(/ x)` is `(/ 1 x)`
+    let (mut quotient, start) = if args.len() == 1 {
+        (Number::Integer(1), 0)
+    } else {
+        (try!(as_number(args, 0)), 1)
+    };
+
+    for index in start..args.len() {
+        let divisor = try!(as_number(args, index));
+
+        // NB float division by zero follows IEEE 754, e.g. `(/ 1.0 0)` is infinity
+        match (&quotient, &divisor) {
+            (&Number::BigInt(_), &Number::Integer(0)) |
+            (&Number::Integer(_), &Number::Integer(0)) => {
+                return Err(Failure::new(Some(index), Error_::DivisionByZero))
+            },
+            _ => {},
+        }
+
+        // NB `i64::MIN / -1` overflows, and becomes a big integer
+        quotient = combine(
+            quotient,
+            divisor,
+            &|a, b| a.checked_div(b),
+            &|a, b| a / b,
+            &|a, b| a / b,
+        );
+    }
+
+    Ok(quotient.into_value())
+}
+
+fn eq(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::AtLeast(1)));
+
+    Ok(Value::Bool(args.windows(2).all(|pair| pair[0] == pair[1])))
+}
+
+fn eval(
+    args: &[Value],
+    env: &Env,
+    span: Span,
+    map: &mut SourceMap,
+    interner: &mut Interner,
+) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(1)));
+
+    // NB runtime values in the form, e.g. lists created by `list`, are reported at the call
+    let form = ::eval::to_expr(&args[0], span, interner);
+
+    // NB forms are always evaluated in the global environment
+    Ok(try!(::eval::expr(&form, &env.root(), map, interner)))
+}
+
+/// Raises `base` to the power of `exp` exactly, the exponent is the second argument of `pow`
+fn exact_pow(base: &BigInt, exp: &BigInt) -> Result<Number, Failure> {
+    // NB the powers of 0, 1 and -1 only depend on whether the exponent is zero, odd or even
+    if base.bits() <= 1 {
+        let exp = if exp.is_zero() { 0 } else if exp.is_odd() { 1 } else { 2 };
+
+        return Ok(Number::from_bigint(base.pow(exp)))
+    }
+
+    // NB the power has at most `bits(base) * exp` bits
+    match exp.to_i64() {
+        Some(exp) if exp as u64 <= MAX_POWER_BITS / base.bits() => {
+            Ok(Number::from_bigint(base.pow(exp as u64)))
+        },
+        _ => Err(Failure::new(Some(1), Error_::Domain)),
     }
 }
 
-fn add(args: &[Value]) -> Option<Value> {
-    match args {
-        [Value::Integer(a), Value::Integer(b)] => Some(Value::Integer(a + b)),
-        _ => None,
+fn first(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(1)));
+
+    Ok(try!(as_seq(args, 0)).first().cloned().unwrap_or(Value::Nil))
+}
+
+fn floor(args: &[Value]) -> Result<Value, Failure> {
+    rounding(args, |x| x.floor())
+}
+
+/// Folds the numeric arguments that start at `start` with `combine`
+fn fold<F, G, H>(
+    args: &[Value],
+    start: usize,
+    init: Number,
+    integer: F,
+    bigint: G,
+    float: H,
+) -> Result<Value, Failure> where
+    F: Fn(i64, i64) -> Option<i64>,
+    G: Fn(&BigInt, &BigInt) -> BigInt,
+    H: Fn(f64, f64) -> f64,
+{
+    let mut acc = init;
+
+    for index in start..args.len() {
+        acc = combine(acc, try!(as_number(args, index)), &integer, &bigint, &float);
     }
+
+    Ok(acc.into_value())
+}
+
+fn ge(args: &[Value]) -> Result<Value, Failure> {
+    monotonic(args, |ordering| ordering != Ordering::Less)
 }
 
-fn div(args: &[Value]) -> Option<Value> {
-    match args {
-        [Value::Integer(a), Value::Integer(b)] => Some(Value::Integer(a / b)),
-        _ => None,
+fn get(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(2)));
+
+    match args[0] {
+        Value::Nil => Ok(Value::Nil),
+        _ => Ok(try!(as_map(args, 0)).get(&args[1]).cloned().unwrap_or(Value::Nil)),
     }
 }
 
-fn ge(args: &[Value]) -> Option<Value> {
-    match args {
-        [Value::Integer(a), Value::Integer(b)] => Some(Value::Bool(a >= b)),
-        _ => None,
+fn gt(args: &[Value]) -> Result<Value, Failure> {
+    monotonic(args, |ordering| ordering == Ordering::Greater)
+}
+
+fn hash_map(args: &[Value]) -> Result<Value, Failure> {
+    let mut map = HashMap::with_capacity(args.len() / 2);
+
+    for pair in try!(pairs(args, 0)) {
+        map.insert(pair[0].clone(), pair[1].clone());
+    }
+
+    Ok(Value::Map(map))
+}
+
+fn is_atom(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(1)));
+
+    match args[0] {
+        Value::Atom(_) => Ok(Value::Bool(true)),
+        _ => Ok(Value::Bool(false)),
+    }
+}
+
+fn is_empty(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(1)));
+
+    Ok(Value::Bool(try!(as_seq(args, 0)).is_empty()))
+}
+
+fn is_list(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(1)));
+
+    match args[0] {
+        Value::List(..) => Ok(Value::Bool(true)),
+        _ => Ok(Value::Bool(false)),
+    }
+}
+
+fn keys(
+    args: &[Value],
+    _: &Env,
+    _: Span,
+    _: &mut SourceMap,
+    interner: &mut Interner,
+) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(1)));
+
+    let entries = ::eval::entries(try!(as_map(args, 0)), interner);
+
+    Ok(Value::List(entries.into_iter().map(|entry| entry.0.clone()).collect(), Span::dummy()))
+}
+
+fn le(args: &[Value]) -> Result<Value, Failure> {
+    monotonic(args, |ordering| ordering != Ordering::Greater)
+}
+
+fn list(args: &[Value]) -> Result<Value, Failure> {
+    Ok(Value::List(args.to_vec(), Span::dummy()))
+}
+
+fn load_file(
+    args: &[Value],
+    env: &Env,
+    _: Span,
+    map: &mut SourceMap,
+    interner: &mut Interner,
+) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(1)));
+
+    let path = try!(as_string(args, 0));
+    let mut string = String::new();
+
+    if let Err(error) = File::open(path).and_then(|mut file| file.read_to_string(&mut string)) {
+        return Err(Failure::new(None, Error_::Io(error.kind())))
+    }
+
+    let file = map.add(String::from_str(path), string);
+    let exprs = try!(parse::exprs(map.source(file), interner).map_err(|error| {
+        error.map(Error_::Syntax)
+    }));
+    let env = env.root();
+
+    for expr in &exprs {
+        try!(::eval::expr(expr, &env, map, interner));
     }
+
+    Ok(Value::Nil)
+}
+
+fn lt(args: &[Value]) -> Result<Value, Failure> {
+    monotonic(args, |ordering| ordering == Ordering::Less)
+}
+
+/// The argument at `index` is `value` but a value of the `expected` type was expected
+fn mismatch(index: usize, expected: Type, value: &Value) -> Failure {
+    Failure::new(Some(index), Error_::TypeMismatch(expected, value.type_()))
 }
 
-fn gt(args: &[Value]) -> Option<Value> {
-    match args {
-        [Value::Integer(a), Value::Integer(b)] => Some(Value::Bool(a > b)),
-        _ => None,
+/// Checks that every pair of adjacent numeric arguments is ordered in a way that satisfies `f`
+fn monotonic<F>(args: &[Value], f: F) -> Result<Value, Failure> where F: Fn(Ordering) -> bool {
+    try!(arity(args, Arity::AtLeast(1)));
+
+    let mut numbers = Vec::with_capacity(args.len());
+
+    for index in 0..args.len() {
+        numbers.push(try!(as_number(args, index)));
     }
+
+    Ok(Value::Bool(numbers.windows(2).all(|pair| {
+        compare(&pair[0], &pair[1]).map_or(false, |ordering| f(ordering))
+    })))
 }
 
-fn le(args: &[Value]) -> Option<Value> {
-    match args {
-        [Value::Integer(a), Value::Integer(b)] => Some(Value::Bool(a <= b)),
-        _ => None,
+fn mul(args: &[Value]) -> Result<Value, Failure> {
+    fold(args, 0, Number::Integer(1), |a, b| a.checked_mul(b), |a, b| a * b, |a, b| a * b)
+}
+
+fn nth(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(2)));
+
+    let elems = try!(as_seq(args, 0));
+    let index = match args[1] {
+        // NB a big integer is out of the bounds of any sequence
+        Value::BigInt(_) => return Err(Failure::new(Some(1), Error_::IndexOutOfBounds)),
+        _ => try!(as_integer(args, 1)),
+    };
+
+    if index >= 0 && (index as usize) < elems.len() {
+        Ok(elems[index as usize].clone())
+    } else {
+        Err(Failure::new(Some(1), Error_::IndexOutOfBounds))
     }
 }
 
-fn lt(args: &[Value]) -> Option<Value> {
-    match args {
-        [Value::Integer(a), Value::Integer(b)] => Some(Value::Bool(a < b)),
-        _ => None,
+/// Splits the arguments that start at `index` into key/value pairs
+fn pairs(args: &[Value], index: usize) -> Result<Chunks<Value>, Failure> {
+    let args = &args[index..];
+
+    if args.len() % 2 == 0 {
+        Ok(args.chunks(2))
+    } else {
+        // NB the last key is missing its value
+        Err(Failure::new(Some(index + args.len() - 1), Error_::ExpectedMapValue))
     }
 }
 
-fn mul(args: &[Value]) -> Option<Value> {
-    match args {
-        [Value::Integer(a), Value::Integer(b)] => Some(Value::Integer(a * b)),
-        _ => None,
+fn pow(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(2)));
+
+    let (base, exp) = (try!(as_number(args, 0)), try!(as_number(args, 1)));
+
+    // NB integers raised to negative exponents are fractions, so they produce a float
+    let power = match (base.to_bigint(), exp.to_bigint()) {
+        (Some(ref base), Some(ref exp)) if !exp.is_negative() => try!(exact_pow(base, exp)),
+        _ => Number::Float(base.to_float().powf(exp.to_float())),
+    };
+
+    Ok(power.into_value())
+}
+
+fn read_string(
+    args: &[Value],
+    _: &Env,
+    _: Span,
+    _: &mut SourceMap,
+    interner: &mut Interner,
+) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(1)));
+
+    match parse::exprs(Source::new(try!(as_string(args, 0))), interner) {
+        // NB the span of the error points into the string, so the error is reported at the call
+        Err(error) => Err(Failure::new(None, Error_::Syntax(error.node))),
+        // NB only the first form is read, a string without forms reads as `nil`
+        Ok(forms) => match forms.first() {
+            None => Ok(Value::Nil),
+            Some(form) => Ok(::eval::forget_spans(try!(::eval::quote(form, interner)))),
+        },
     }
 }
 
-fn sub(args: &[Value]) -> Option<Value> {
-    match args {
-        [Value::Integer(a), Value::Integer(b)] => Some(Value::Integer(a - b)),
-        _ => None,
+fn reset(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(2)));
+
+    try!(as_atom(args, 0)).set(args[1].clone());
+
+    Ok(args[1].clone())
+}
+
+fn rest(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(1)));
+
+    let elems = try!(as_seq(args, 0));
+
+    Ok(Value::List(elems.iter().skip(1).cloned().collect(), Span::dummy()))
+}
+
+fn round(args: &[Value]) -> Result<Value, Failure> {
+    rounding(args, |x| x.round())
+}
+
+/// Rounds the only argument with `f`. Integers are already round, so they are returned as they are
+fn rounding<F>(args: &[Value], f: F) -> Result<Value, Failure> where F: Fn(f64) -> f64 {
+    try!(arity(args, Arity::Exactly(1)));
+
+    match try!(as_number(args, 0)) {
+        Number::Float(float) => Ok(Value::Float(f(float))),
+        integer => Ok(integer.into_value()),
     }
 }
+
+fn sin(args: &[Value]) -> Result<Value, Failure> {
+    unary(args, |x| x.sin())
+}
+
+fn sqrt(args: &[Value]) -> Result<Value, Failure> {
+    unary(args, |x| x.sqrt())
+}
+
+fn sub(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::AtLeast(1)));
+
+    // NB `(- x)` is `(- 0 x)`
+    let (init, start) = if args.len() == 1 {
+        (Number::Integer(0), 0)
+    } else {
+        (try!(as_number(args, 0)), 1)
+    };
+
+    fold(args, start, init, |a, b| a.checked_sub(b), |a, b| a - b, |a, b| a - b)
+}
+
+/// Replaces the value of an atom with the result of calling a function with the value of the atom
+/// and the rest of the arguments
+fn swap(
+    args: &[Value],
+    env: &Env,
+    span: Span,
+    map: &mut SourceMap,
+    interner: &mut Interner,
+) -> Result<Value, Failure> {
+    try!(arity(args, Arity::AtLeast(2)));
+
+    let atom = try!(as_atom(args, 0));
+    let function = match args[1] {
+        Value::Function(ref function) => function,
+        _ => return Err(Failure::new(Some(1), Error_::ExpectedFunction)),
+    };
+
+    let mut values = Vec::with_capacity(args.len() - 1);
+
+    values.push(atom.get());
+    values.extend(args[2..].iter().cloned());
+
+    let value = try!(::eval::call(function, values, env, span, map, interner).map_err(|failure| {
+        // NB the first argument of the function is the value of the atom, the others are the
+        // arguments of `swap!` that follow the function
+        Failure {
+            arg: failure.arg.map(|arg| if arg == 0 { 0 } else { arg + 1 }),
+            ..failure
+        }
+    }));
+
+    atom.set(value.clone());
+
+    Ok(value)
+}
+
+fn tan(args: &[Value]) -> Result<Value, Failure> {
+    unary(args, |x| x.tan())
+}
+
+fn throw(args: &[Value]) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(1)));
+
+    Err(Failure::new(None, Error_::Throw(args[0].clone())))
+}
+
+/// Applies the float function `f` to the only argument
+fn unary<F>(args: &[Value], f: F) -> Result<Value, Failure> where F: Fn(f64) -> f64 {
+    try!(arity(args, Arity::Exactly(1)));
+
+    Ok(Value::Float(f(try!(as_number(args, 0)).to_float())))
+}
+
+fn vals(
+    args: &[Value],
+    _: &Env,
+    _: Span,
+    _: &mut SourceMap,
+    interner: &mut Interner,
+) -> Result<Value, Failure> {
+    try!(arity(args, Arity::Exactly(1)));
+
+    let entries = ::eval::entries(try!(as_map(args, 0)), interner);
+
+    Ok(Value::List(entries.into_iter().map(|entry| entry.1.clone()).collect(), Span::dummy()))
+}
@@ -1,13 +1,23 @@
 //! Evaluation
 
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::f64;
 use std::fmt;
+use std::hash::{Hash, Hasher, SipHasher};
+use std::io;
+use std::mem;
 use std::ops::Deref;
+use std::ptr;
+use std::raw::TraitObject;
 
 use rc::Rc;
 
-use eval::env::{Env, Stack};
+use eval::env::Env;
 use syntax::ast::{Expr, Expr_, Operator};
-use syntax::codemap::{Source, Spanned};
+use syntax::codemap::{SourceMap, Span, Spanned};
+use syntax;
+use util::bigint::BigInt;
 use util::interner::{Interner, Name};
 
 pub mod env;
@@ -15,87 +25,466 @@ pub mod env;
 /// Spanned error
 pub type Error = Spanned<Error_>;
 
+/// The number of arguments a function accepts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arity {
+    /// `n` or more arguments
+    AtLeast(usize),
+    /// Exactly `n` arguments
+    Exactly(usize),
+}
+
+impl Arity {
+    /// Checks if a function with this arity can be called with `n` arguments
+    pub fn accepts(&self, n: usize) -> bool {
+        match *self {
+            Arity::AtLeast(min) => n >= min,
+            Arity::Exactly(exact) => n == exact,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (prefix, n) = match *self {
+            Arity::AtLeast(n) => ("at least ", n),
+            Arity::Exactly(n) => ("", n),
+        };
+
+        write!(f, "{}{} argument{}", prefix, n, if n == 1 { "" } else { "s" })
+    }
+}
+
+/// A mutable reference to a value, created with `atom`
+///
+/// Cloning an atom doesn't copy its value, instead the clone refers to the same value
+#[derive(Clone)]
+pub struct Atom(Rc<RefCell<Value>>);
+
+impl Atom {
+    fn new(value: Value) -> Atom {
+        Atom(Rc::new(RefCell::new(value)))
+    }
+
+    /// Returns the address of the atom's value, this identifies the atom
+    fn addr(&self) -> *const RefCell<Value> {
+        self.0.deref()
+    }
+
+    /// Returns a copy of the current value of the atom
+    pub fn get(&self) -> Value {
+        self.0.borrow().clone()
+    }
+
+    /// Replaces the value of the atom
+    pub fn set(&self, value: Value) {
+        *self.0.borrow_mut() = value;
+    }
+}
+
+impl fmt::Debug for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.addr().fmt(f)
+    }
+}
+
+/// Atoms are compared by identity: an atom is only equal to itself
+impl PartialEq for Atom {
+    fn eq(&self, other: &Atom) -> bool {
+        self.addr() == other.addr()
+    }
+}
+
+impl Eq for Atom {}
+
+impl Hash for Atom {
+    fn hash<H>(&self, state: &mut H) where H: Hasher {
+        self.addr().hash(state)
+    }
+}
+
+/// An error raised by a built-in function
+#[derive(Debug)]
+pub struct Failure {
+    /// The index of the offending argument, or `None` if the error is about the call as a whole
+    pub arg: Option<usize>,
+    /// The error
+    pub error: Error_,
+    /// Where the error was raised, if that's neither the call nor one of its arguments, e.g.
+    /// within the form passed to `eval`. Takes precedence over `arg`
+    pub span: Option<Span>,
+}
+
+impl Failure {
+    /// Creates a new failure
+    pub fn new(arg: Option<usize>, error: Error_) -> Failure {
+        Failure {
+            arg: arg,
+            error: error,
+            span: None,
+        }
+    }
+
+    /// Turns the failure of the call at `span` into an error. `arg_span` maps the index of an
+    /// argument to its span
+    fn at<F>(self, span: Span, arg_span: F) -> Error where F: Fn(usize) -> Span {
+        let span = match self.span {
+            None => self.arg.map_or(span, arg_span),
+            Some(span) => span,
+        };
+
+        Spanned::new(span, self.error)
+    }
+}
+
+/// Errors raised while evaluating code on behalf of a built-in function keep their span
+impl From<Error> for Failure {
+    fn from(error: Error) -> Failure {
+        Failure {
+            arg: None,
+            error: error.node,
+            span: Some(error.span),
+        }
+    }
+}
+
+/// The signature of built-in functions
+///
+/// Besides its arguments, a built-in function receives the environment and the span of the call,
+/// which it needs to evaluate code, as `eval` does
+pub type Builtin =
+    Fn(&[Value], &Env, Span, &mut SourceMap, &mut Interner) -> Result<Value, Failure>;
+
 /// A built-in function or a user defined lambda
 #[derive(Clone)]
-pub struct Function(Rc<Fn(&[Value]) -> Option<Value>>);
+pub enum Function {
+    /// A function implemented in Rust: `+`
+    Builtin(Rc<Builtin>),
+    /// A closure created with `fn*`: `(fn* [x] (* x x))`
+    Lambda(Rc<Lambda>),
+    /// A lambda that was turned into a macro with `defmacro!`
+    Macro(Rc<Lambda>),
+}
 
 impl Function {
-    fn new<F>(f: F) -> Function where F: Fn(&[Value]) -> Option<Value> + 'static {
-        let boxed_f: Box<Fn(&[Value]) -> Option<Value>> = Box::new(f);
-        Function(Rc::from(boxed_f))
+    /// Creates a built-in function that only depends on its arguments
+    fn new<F>(f: F) -> Function where F: Fn(&[Value]) -> Result<Value, Failure> + 'static {
+        Function::with_interpreter(move |args, _, _, _, _| f(args))
+    }
+
+    /// Creates a built-in function that evaluates code
+    fn with_interpreter<F>(f: F) -> Function where
+        F: Fn(&[Value], &Env, Span, &mut SourceMap, &mut Interner) -> Result<Value, Failure>,
+        F: 'static,
+    {
+        let boxed_f: Box<Builtin> = Box::new(f);
+        Function::Builtin(Rc::from(boxed_f))
+    }
+
+    /// Returns the addresses of the function's data and vtable, these identify the function
+    fn addr(&self) -> (*mut (), *mut ()) {
+        match *self {
+            Function::Builtin(ref builtin) => {
+                let TraitObject { data, vtable } = unsafe {
+                    mem::transmute(builtin.deref())
+                };
+
+                (data, vtable)
+            },
+            Function::Lambda(ref lambda) | Function::Macro(ref lambda) => {
+                (lambda.deref() as *const Lambda as *mut (), ptr::null_mut())
+            },
+        }
     }
 }
 
 impl fmt::Debug for Function {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use std::mem;
-        use std::raw::TraitObject;
+        self.addr().0.fmt(f)
+    }
+}
 
-        let TraitObject { data, .. } = unsafe {
-            mem::transmute(self.0.deref())
-        };
+/// Functions are compared by identity: a function is only equal to itself
+impl PartialEq for Function {
+    fn eq(&self, other: &Function) -> bool {
+        self.addr() == other.addr()
+    }
+}
+
+impl Eq for Function {}
 
-        data.fmt(f)
+impl Hash for Function {
+    fn hash<H>(&self, state: &mut H) where H: Hasher {
+        self.addr().hash(state)
     }
 }
 
-impl Deref for Function {
-    type Target = Fn(&[Value]) -> Option<Value> + 'static;
+/// A user defined function
+pub struct Lambda {
+    body: Expr,
+    /// The environment the lambda was created in
+    env: Env,
+    params: Vec<Name>,
+}
 
-    fn deref(&self) -> &(Fn(&[Value]) -> Option<Value> + 'static) {
-        self.0.deref()
+impl Lambda {
+    /// Binds `args` to the parameters of the lambda in a new environment. Fails if the number of
+    /// arguments doesn't match the number of parameters
+    fn bind(&self, args: Vec<Value>) -> Result<Env, Error_> {
+        if args.len() != self.params.len() {
+            return Err(Error_::ArityMismatch(Arity::Exactly(self.params.len()), args.len()))
+        }
+
+        let env = self.env.push();
+
+        for (param, arg) in self.params.iter().zip(args.into_iter()) {
+            env.insert(*param, arg);
+        }
+
+        Ok(env)
     }
 }
 
 /// Evaluation error
 #[derive(Debug, PartialEq)]
 pub enum Error_ {
+    /// `(count 1 2)`, the arity of the function and the number of arguments it was called with
+    ArityMismatch(Arity, usize),
+    /// `(/ 1 0)`
+    DivisionByZero,
+    /// `(pow 2 1000000)`, an argument for which the function can't produce a result
+    Domain,
     /// `()`
     EmptyList,
-    /// `(a 1 2)` where `a = 2`
+    /// `(a 1 2)` where `a = 2`, `(1 2 3)`
     ExpectedFunction,
-    /// `(1 2 3)`
+    /// `(hash-map :a 1 :b)`
+    ExpectedMapValue,
+    /// `(def! 1 2)`
     ExpectedSymbol,
+    /// `(nth [1 2] 2)`
+    IndexOutOfBounds,
+    /// An expression that couldn't be parsed, see `parse::recover`
+    InvalidSyntax,
+    /// `(load-file "missing.mal")`
+    Io(io::ErrorKind),
+    /// An error raised while expanding a macro call, or while evaluating its expansion. The span
+    /// of the error is the span of the macro call
+    MacroExpansion(Box<Error_>),
+    /// `(read-string "(+ 1")`
+    Syntax(syntax::Error_),
+    /// `(throw :oops)`, a value thrown by lisp code
+    Throw(Value),
+    /// `(first 1)`, the expected type and the type of the value that was found
+    TypeMismatch(Type, Type),
     /// `(foo 1 2)`
     UndefinedSymbol,
     /// `(+ 1)`
     UnsupportedOperation,
 }
 
+impl Error_ {
+    /// Converts the error into a value that can be caught by `catch*`
+    ///
+    /// Thrown values are returned as they are, other errors become their message as a string
+    pub fn into_value(self) -> Value {
+        match self {
+            Error_::MacroExpansion(error) => error.into_value(),
+            Error_::Throw(value) => value,
+            error => Value::String(error.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Error_ {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error_::ArityMismatch(arity, n) => write!(f, "expected {}, found {}", arity, n),
+            Error_::DivisionByZero => f.write_str("attempted to divide by zero"),
+            Error_::Domain => f.write_str("argument out of domain"),
+            Error_::EmptyList => f.write_str("empty list"),
+            Error_::ExpectedFunction => f.write_str("expected function"),
+            Error_::ExpectedMapValue => f.write_str("expected a value for this key"),
+            Error_::ExpectedSymbol => f.write_str("expected symbol"),
+            Error_::IndexOutOfBounds => f.write_str("index out of bounds"),
+            Error_::InvalidSyntax => f.write_str("invalid syntax"),
+            Error_::Io(_) => f.write_str("couldn't read the file"),
+            Error_::MacroExpansion(ref error) => fmt::Display::fmt(error, f),
+            Error_::Syntax(ref error) => f.write_str(error.description()),
+            Error_::Throw(_) => f.write_str("uncaught exception"),
+            Error_::TypeMismatch(expected, found) => {
+                write!(f, "expected {}, found {}", expected.as_str(), found.as_str())
+            },
+            Error_::UndefinedSymbol => f.write_str("undefined symbol"),
+            Error_::UnsupportedOperation => f.write_str("unsupported operation"),
+        }
+    }
+}
+
+/// The type of a value
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Type {
+    /// `(atom 1)`
+    Atom,
+    /// `true`
+    Bool,
+    /// `1.5`
+    Float,
+    /// `+`
+    Function,
+    /// `123`, small integers and big integers have the same type
+    Integer,
+    /// `:a`
+    Keyword,
+    /// `(1 2)`
+    List,
+    /// `{:a 1}`
+    Map,
+    /// `nil`
+    Nil,
+    /// An integer or a float. No value has this type, it's only used as an expected type
+    Number,
+    /// A list, a vector or `nil`. No value has this type, it's only used as an expected type
+    Sequence,
+    /// `"a"`
+    String,
+    /// `'a`
+    Symbol,
+    /// `[1 2]`
+    Vector,
+}
+
+impl Type {
+    /// Returns the name of the type
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Type::Atom => "atom",
+            Type::Bool => "bool",
+            Type::Float => "float",
+            Type::Function => "function",
+            Type::Integer => "integer",
+            Type::Keyword => "keyword",
+            Type::List => "list",
+            Type::Map => "map",
+            Type::Nil => "nil",
+            Type::Number => "number",
+            Type::Sequence => "sequence",
+            Type::String => "string",
+            Type::Symbol => "symbol",
+            Type::Vector => "vector",
+        }
+    }
+}
+
 /// A value
+///
+/// Symbols, lists and vectors carry the span of the source code they come from, or the dummy span
+/// if they were created by a function, e.g. by `list`
 #[derive(Clone, Debug)]
 pub enum Value {
+    /// `(atom 1)`
+    Atom(Atom),
+    /// `9223372036854775808`, only used for integers that don't fit in an `i64`
+    BigInt(BigInt),
     /// `true` or `false`
     Bool(bool),
+    /// `1.5`
+    Float(f64),
     /// `+`
     Function(Function),
     /// `123`
     Integer(i64),
     /// `:a`
     Keyword(Name),
+    /// `(1 "two" (3))`
+    List(Vec<Value>, Span),
+    /// `{:a 1 "b" 2}`
+    Map(HashMap<Value, Value>),
     ///  `nil`
     Nil,
     /// `"Hello, world!"`
     String(String),
+    /// `'a`
+    Symbol(Name, Span),
     /// `[1 "two" [3]]`
-    Vector(Vec<Value>),
+    Vector(Vec<Value>, Span),
 }
 
 impl Value {
+    /// Returns the type of this value
+    pub fn type_(&self) -> Type {
+        match *self {
+            Value::Atom(_) => Type::Atom,
+            Value::BigInt(_) => Type::Integer,
+            Value::Bool(_) => Type::Bool,
+            Value::Float(_) => Type::Float,
+            Value::Function(_) => Type::Function,
+            Value::Integer(_) => Type::Integer,
+            Value::Keyword(_) => Type::Keyword,
+            Value::List(..) => Type::List,
+            Value::Map(_) => Type::Map,
+            Value::Nil => Type::Nil,
+            Value::String(_) => Type::String,
+            Value::Symbol(..) => Type::Symbol,
+            Value::Vector(..) => Type::Vector,
+        }
+    }
+
     /// Formats this value
     pub fn display(&self, interner: &Interner) -> String {
         let mut string = String::new();
-        self.display_(interner, &mut string);
+        self.display_(interner, &mut vec![], &mut string);
         string
     }
 
-    fn display_(&self, interner: &Interner, string: &mut String) {
+    /// `atoms` are the atoms whose values are being formatted
+    fn display_(&self, interner: &Interner, atoms: &mut Vec<Atom>, string: &mut String) {
         use std::fmt::Write;
 
+        fn seq(elems: &[Value], interner: &Interner, atoms: &mut Vec<Atom>, string: &mut String) {
+            let mut is_first = true;
+
+            for elem in elems {
+                if is_first {
+                    is_first = false;
+                } else {
+                    string.push(' ');
+                }
+
+                elem.display_(interner, atoms, string)
+            }
+        }
+
         match *self {
+            Value::Atom(ref atom) => {
+                // NB an atom can (indirectly) contain itself, e.g. after `(reset! a [a])`, its
+                // value is not formatted again
+                if atoms.contains(atom) {
+                    string.push_str("(atom ...)");
+                } else {
+                    atoms.push(atom.clone());
+                    string.push_str("(atom ");
+                    atom.get().display_(interner, atoms, string);
+                    string.push(')');
+                    atoms.pop();
+                }
+            },
+            Value::BigInt(ref integer) => {
+                write!(string, "{}", integer).ok();
+            },
             Value::Bool(bool) => {
                 write!(string, "{}", bool).ok();
             },
+            Value::Float(float) => {
+                let start = string.len();
+
+                write!(string, "{}", float).ok();
+
+                // NB always print a decimal point, so floats don't look like integers
+                if float.is_finite() && !string[start..].contains('.') {
+                    string.push_str(".0");
+                }
+            },
             Value::Function(ref function) => {
                 write!(string, "<function at {:?}>", function).ok();
             },
@@ -103,78 +492,458 @@ impl Value {
                 write!(string, "{}", integer).ok();
             },
             Value::Keyword(ref name) => string.push_str(&interner.get(name)),
-            Value::Nil => string.push_str("nil"),
-            Value::String(ref s) => string.push_str(s),
-            Value::Vector(ref elems) => {
-                string.push('[');
+            Value::List(ref elems, _) => {
+                string.push('(');
+                seq(elems, interner, atoms, string);
+                string.push(')');
+            },
+            Value::Map(ref map) => {
+                string.push('{');
 
                 let mut is_first = true;
-                for elem in elems {
+                for (key, value) in entries(map, interner) {
                     if is_first {
                         is_first = false;
                     } else {
                         string.push(' ');
                     }
 
-                    elem.display_(interner, string)
+                    key.display_(interner, atoms, string);
+                    string.push(' ');
+                    value.display_(interner, atoms, string)
                 }
 
+                string.push('}');
+            },
+            Value::Nil => string.push_str("nil"),
+            Value::String(ref s) => {
+                string.push('"');
+
+                for c in s.chars() {
+                    match c {
+                        '"' => string.push_str("\\\""),
+                        '\\' => string.push_str("\\\\"),
+                        '\n' => string.push_str("\\n"),
+                        '\t' => string.push_str("\\t"),
+                        c => string.push(c),
+                    }
+                }
+
+                string.push('"');
+            },
+            Value::Symbol(ref name, _) => string.push_str(&interner.get(name)),
+            Value::Vector(ref elems, _) => {
+                string.push('[');
+                seq(elems, interner, atoms, string);
                 string.push(']');
-            }
+            },
+        }
+    }
+}
+
+/// Values are compared structurally and spans are ignored:
+///
+/// - Integers, keywords, strings and symbols are equal if they have the same content
+/// - Floats are equal if they have the same value, so `0.0` is equal to `-0.0`. Unlike IEEE 754,
+///   `NaN` is equal to itself, otherwise `Value` wouldn't be a lawful `Eq` map key
+/// - Integers and floats are never equal, e.g. `(= 1 1.0)` is false
+/// - Big integers are only used for integers that don't fit in an `i64`, so a big integer is never
+///   equal to a small integer
+/// - Lists and vectors are sequences, two sequences are equal if they have equal elements, e.g.
+///   `(= [1 2] (list 1 2))` is true
+/// - Maps are equal if they have equal keys that map to equal values
+/// - Atoms and functions are compared by identity, an atom or a function is only equal to itself
+///
+/// Values of different types are never equal
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (&Value::Atom(ref a), &Value::Atom(ref b)) => a == b,
+            (&Value::BigInt(ref a), &Value::BigInt(ref b)) => a == b,
+            (&Value::Bool(a), &Value::Bool(b)) => a == b,
+            (&Value::Float(a), &Value::Float(b)) => a == b || a.is_nan() && b.is_nan(),
+            (&Value::Function(ref a), &Value::Function(ref b)) => a == b,
+            (&Value::Integer(a), &Value::Integer(b)) => a == b,
+            (&Value::Keyword(a), &Value::Keyword(b)) => a == b,
+            (&Value::List(ref a, _), &Value::List(ref b, _)) |
+            (&Value::List(ref a, _), &Value::Vector(ref b, _)) |
+            (&Value::Vector(ref a, _), &Value::List(ref b, _)) |
+            (&Value::Vector(ref a, _), &Value::Vector(ref b, _)) => a == b,
+            (&Value::Map(ref a), &Value::Map(ref b)) => a == b,
+            (&Value::Nil, &Value::Nil) => true,
+            (&Value::String(ref a), &Value::String(ref b)) => a == b,
+            (&Value::Symbol(a, _), &Value::Symbol(b, _)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H>(&self, state: &mut H) where H: Hasher {
+        match *self {
+            Value::Atom(ref atom) => {
+                0u8.hash(state);
+                atom.hash(state)
+            },
+            // NB never equal to a small integer, so the tag can be shared with `Integer`
+            Value::BigInt(ref integer) => {
+                4u8.hash(state);
+                integer.hash(state)
+            },
+            Value::Bool(bool) => {
+                1u8.hash(state);
+                bool.hash(state)
+            },
+            Value::Float(float) => {
+                2u8.hash(state);
+
+                let float = match float {
+                    // NB `0.0` and `-0.0` are equal, so they must hash the same
+                    float if float == 0.0 => 0.0,
+                    // NB and so are all the `NaN`s
+                    float if float.is_nan() => f64::NAN,
+                    float => float,
+                };
+                let bits: u64 = unsafe { mem::transmute(float) };
+
+                bits.hash(state)
+            },
+            Value::Function(ref function) => {
+                3u8.hash(state);
+                function.hash(state)
+            },
+            Value::Integer(integer) => {
+                4u8.hash(state);
+                integer.hash(state)
+            },
+            Value::Keyword(ref name) => {
+                5u8.hash(state);
+                name.hash(state)
+            },
+            // NB lists and vectors with equal elements are equal, so they must hash the same
+            Value::List(ref elems, _) | Value::Vector(ref elems, _) => {
+                6u8.hash(state);
+                elems.hash(state)
+            },
+            Value::Map(ref map) => {
+                7u8.hash(state);
+
+                // NB the iteration order of a map is unspecified, so the hashes of its entries
+                // are combined with an operation that doesn't depend on the order
+                let mut sum = 0u64;
+                for entry in map {
+                    let mut hasher = SipHasher::new();
+                    entry.hash(&mut hasher);
+                    sum = sum.wrapping_add(hasher.finish());
+                }
+
+                sum.hash(state)
+            },
+            Value::Nil => 8u8.hash(state),
+            Value::String(ref string) => {
+                9u8.hash(state);
+                string.hash(state)
+            },
+            Value::Symbol(ref name, _) => {
+                10u8.hash(state);
+                name.hash(state)
+            },
         }
     }
 }
 
+/// Calls a lambda with `args`. If the lambda can't be called with `args`, the error is reported at
+/// `span`, the span of the call
+fn apply(
+    lambda: &Lambda,
+    args: Vec<Value>,
+    span: Span,
+    map: &mut SourceMap,
+    interner: &mut Interner,
+) -> Result<Value, Error> {
+    match lambda.bind(args) {
+        Err(error) => Err(Spanned::new(span, error)),
+        Ok(ref env) => ::eval::expr(&lambda.body, env, map, interner),
+    }
+}
+
+/// Calls a built-in function or a lambda with `args` on behalf of the built-in function called
+/// at `span`, e.g. `swap!`
+fn call(
+    function: &Function,
+    args: Vec<Value>,
+    env: &Env,
+    span: Span,
+    map: &mut SourceMap,
+    interner: &mut Interner,
+) -> Result<Value, Failure> {
+    match *function {
+        Function::Builtin(ref builtin) => builtin(&args, env, span, map, interner),
+        Function::Lambda(ref lambda) => match lambda.bind(args) {
+            Err(error) => Err(Failure::new(None, error)),
+            Ok(ref env) => Ok(try!(::eval::expr(&lambda.body, env, map, interner))),
+        },
+        // NB macros can't be called with evaluated arguments
+        Function::Macro(_) => Err(Failure::new(None, Error_::ExpectedFunction)),
+    }
+}
+
 /// Evaluates an expression
-pub fn expr(expr: &Expr, source: &Source, env: &mut Stack) -> Result<Value, Error> {
+pub fn expr(
+    expr: &Expr,
+    env: &Env,
+    map: &mut SourceMap,
+    interner: &mut Interner,
+) -> Result<Value, Error> {
+    let (mut lambda, mut env) = match try!(expr_(expr, env, map, interner)) {
+        Tail::Call(lambda, env) => (lambda, env),
+        Tail::Value(value) => return Ok(value),
+    };
+
+    // NB Calls in tail position are evaluated in this loop rather than recursively, so the native
+    // stack doesn't grow with the depth of (tail) recursion
+    loop {
+        match try!(expr_(&lambda.body, &env, map, interner)) {
+            Tail::Call(next_lambda, next_env) => {
+                lambda = next_lambda;
+                env = next_env;
+            },
+            Tail::Value(value) => return Ok(value),
+        }
+    }
+}
+
+/// The result of evaluating an expression up to its tail position
+enum Tail {
+    /// The body of `lambda` must be evaluated in `env` to produce the value of the expression
+    Call(Rc<Lambda>, Env),
+    /// The expression has been fully evaluated
+    Value(Value),
+}
+
+/// Evaluates an expression, but stops short of calling a lambda in tail position
+fn expr_(
+    expr: &Expr,
+    env: &Env,
+    map: &mut SourceMap,
+    interner: &mut Interner,
+) -> Result<Tail, Error> {
     macro_rules! err {
         ($span:expr, $err:ident) => {
             Err(Spanned::new($span.span, Error_::$err))
         }
     }
 
-    match expr.node {
-        Expr_::Bool(bool) => Ok(Value::Bool(bool)),
-        Expr_::Integer(integer) => Ok(Value::Integer(integer)),
-        Expr_::Keyword(name) => Ok(Value::Keyword(name)),
-        Expr_::Operator(_) => {
-            // This is a syntax error that gets caught earlier on
-            unreachable!()
-        },
-        Expr_::List(ref exprs) => match &exprs[..] {
-            [] => err!(expr, EmptyList),
-            [ref head, tail..] => match head.node {
-                Expr_::Operator(operator) => {
-                    match operator {
+    let mut expr = expr;
+    let mut env = env.clone();
+
+    loop {
+        let value = match expr.node {
+            Expr_::BigInt(ref integer) => Value::BigInt(integer.clone()),
+            Expr_::Bool(bool) => Value::Bool(bool),
+            // NB only `parse::recover` produces error nodes
+            Expr_::Error => return err!(expr, InvalidSyntax),
+            Expr_::Float(float) => Value::Float(float),
+            Expr_::Integer(integer) => Value::Integer(integer),
+            Expr_::Keyword(name) => Value::Keyword(name),
+            Expr_::Operator(_) => {
+                // This is a syntax error that gets caught earlier on
+                unreachable!()
+            },
+            Expr_::List(ref exprs) => match &exprs[..] {
+                [] => return err!(expr, EmptyList),
+                [ref head, tail..] => match head.node {
+                    Expr_::Operator(operator) => match operator {
+                        Operator::Catch => {
+                            // NB only allowed as the last argument of `try*`
+                            return err!(expr, UnsupportedOperation)
+                        },
                         Operator::Def => {
                             if let [ref symbol, ref expr] = tail {
                                 if let Expr_::Symbol(symbol) = symbol.node {
-                                    let value = try!(::eval::expr(expr, source, env));
+                                    let value = try!(::eval::expr(expr, &env, map, interner));
 
                                     env.insert(symbol, value.clone());
 
-                                    Ok(value)
+                                    value
                                 } else {
-                                    err!(symbol, ExpectedSymbol)
+                                    return err!(symbol, ExpectedSymbol)
                                 }
                             } else {
-                                err!(expr, UnsupportedOperation)
+                                return err!(expr, UnsupportedOperation)
                             }
                         },
-                        Operator::If => {
-                            if let [ref cond, ref then, ref els] = tail {
-                                if match try!(::eval::expr(cond, source, env)) {
-                                    Value::Bool(false) | Value::Nil => false,
-                                    _ => true,
-                                } {
-                                    ::eval::expr(then, source, env)
+                        Operator::Defmacro => {
+                            if let [ref symbol, ref expr] = tail {
+                                if let Expr_::Symbol(symbol) = symbol.node {
+                                    let value = try!(::eval::expr(expr, &env, map, interner));
+                                    let lambda = match value {
+                                        Value::Function(Function::Lambda(lambda)) => lambda,
+                                        _ => return err!(expr, ExpectedFunction),
+                                    };
+                                    let value = Value::Function(Function::Macro(lambda));
+
+                                    env.insert(symbol, value.clone());
+
+                                    value
                                 } else {
-                                    ::eval::expr(els, source, env)
+                                    return err!(symbol, ExpectedSymbol)
+                                }
+                            } else {
+                                return err!(expr, UnsupportedOperation)
+                            }
+                        },
+                        Operator::Do => match tail {
+                            [] => Value::Nil,
+                            [init.., ref last] => {
+                                for expr in init {
+                                    try!(::eval::expr(expr, &env, map, interner));
+                                }
+
+                                expr = last;
+
+                                continue
+                            },
+                        },
+                        Operator::Fn => {
+                            if let [ref params, ref body] = tail {
+                                match params.node {
+                                    Expr_::List(ref params) | Expr_::Vector(ref params) => {
+                                        let mut names = Vec::with_capacity(params.len());
+
+                                        for param in params {
+                                            if let Expr_::Symbol(symbol) = param.node {
+                                                names.push(symbol)
+                                            } else {
+                                                return err!(param, ExpectedSymbol)
+                                            }
+                                        }
+
+                                        let lambda = Lambda {
+                                            body: body.clone(),
+                                            env: env.clone(),
+                                            params: names,
+                                        };
+
+                                        Value::Function(Function::Lambda(Rc::new(lambda)))
+                                    },
+                                    _ => return err!(params, UnsupportedOperation),
+                                }
+                            } else {
+                                return err!(expr, UnsupportedOperation)
+                            }
+                        },
+                        Operator::If => {
+                            let (cond, then, els) = match tail {
+                                [ref cond, ref then] => (cond, then, None),
+                                [ref cond, ref then, ref els] => (cond, then, Some(els)),
+                                _ => return err!(expr, UnsupportedOperation),
+                            };
+
+                            let is_true = match try!(::eval::expr(cond, &env, map, interner)) {
+                                Value::Bool(false) | Value::Nil => false,
+                                _ => true,
+                            };
+
+                            expr = if is_true {
+                                then
+                            } else if let Some(els) = els {
+                                els
+                            } else {
+                                return Ok(Tail::Value(Value::Nil))
+                            };
+
+                            continue
+                        },
+                        Operator::Macroexpand => {
+                            if let [ref form] = tail {
+                                let mut form = try!(quote(form, interner));
+
+                                let span = expr.span;
+
+                                loop {
+                                    match try!(macroexpand_1(&form, &env, span, map, interner)) {
+                                        None => break,
+                                        Some(expansion) => form = expansion,
+                                    }
+                                }
+
+                                form
+                            } else {
+                                return err!(expr, UnsupportedOperation)
+                            }
+                        },
+                        Operator::Macroexpand1 => {
+                            if let [ref form] = tail {
+                                let form = try!(quote(form, interner));
+
+                                match try!(macroexpand_1(&form, &env, expr.span, map, interner)) {
+                                    None => form,
+                                    Some(expansion) => expansion,
                                 }
                             } else {
-                                err!(expr, UnsupportedOperation)
+                                return err!(expr, UnsupportedOperation)
                             }
                         },
+                        Operator::Quasiquote => {
+                            if let [ref expr] = tail {
+                                try!(quasiquote(expr, &env, map, interner))
+                            } else {
+                                return err!(expr, UnsupportedOperation)
+                            }
+                        },
+                        Operator::Quote => {
+                            if let [ref expr] = tail {
+                                try!(quote(expr, interner))
+                            } else {
+                                return err!(expr, UnsupportedOperation)
+                            }
+                        },
+                        Operator::SpliceUnquote | Operator::Unquote => {
+                            // NB only allowed inside a quasiquoted form
+                            return err!(expr, UnsupportedOperation)
+                        },
+                        Operator::Try => match tail {
+                            [ref body] => {
+                                expr = body;
+
+                                continue
+                            },
+                            [ref body, ref catch] => {
+                                let (symbol, handler) = match catch.node {
+                                    Expr_::List(ref exprs) => match &exprs[..] {
+                                        [ref head, ref symbol, ref handler] => match head.node {
+                                            Expr_::Operator(Operator::Catch) => (symbol, handler),
+                                            _ => return err!(catch, UnsupportedOperation),
+                                        },
+                                        _ => return err!(catch, UnsupportedOperation),
+                                    },
+                                    _ => return err!(catch, UnsupportedOperation),
+                                };
+
+                                let symbol = match symbol.node {
+                                    Expr_::Symbol(symbol) => symbol,
+                                    _ => return err!(symbol, ExpectedSymbol),
+                                };
+
+                                match ::eval::expr(body, &env, map, interner) {
+                                    Err(error) => {
+                                        let inner = env.push();
+
+                                        inner.insert(symbol, error.node.into_value());
+
+                                        env = inner;
+                                        expr = handler;
+
+                                        continue
+                                    },
+                                    Ok(value) => value,
+                                }
+                            },
+                            _ => return err!(expr, UnsupportedOperation),
+                        },
                         Operator::Let => {
                             if let [ref list, ref ret] = tail {
                                 match list.node {
@@ -183,77 +952,360 @@ pub fn expr(expr: &Expr, source: &Source, env: &mut Stack) -> Result<Value, Erro
                                             return err!(expr, UnsupportedOperation)
                                         }
 
-                                        let ref mut env = env.push(Env::new());
+                                        let inner = env.push();
 
                                         for binding in bindings.chunks(2) {
-                                            if let [ref symbol, ref expr] = binding {
-                                                if let Expr_::Symbol(symbol) = symbol.node {
-                                                    let value = ::eval::expr(expr, source, env);
-
-                                                    env.insert(symbol, try!(value))
-                                                } else {
-                                                    return err!(symbol, ExpectedSymbol)
-                                                }
-                                            } else {
-                                                // NB because bindings.len() is an even number
-                                                unreachable!();
-                                            }
+                                            // NB because bindings.len() is an even number
+                                            let (symbol, expr) = (&binding[0], &binding[1]);
+
+                                            let symbol = match symbol.node {
+                                                Expr_::Symbol(symbol) => symbol,
+                                                _ => return err!(symbol, ExpectedSymbol),
+                                            };
+                                            let value = ::eval::expr(expr, &inner, map, interner);
+
+                                            inner.insert(symbol, try!(value))
                                         }
 
-                                        ::eval::expr(ret, source, env)
-                                    },
-                                    _ => err!(expr, UnsupportedOperation),
+                                        env = inner;
+                                        expr = ret;
 
+                                        continue
+                                    },
+                                    _ => return err!(expr, UnsupportedOperation),
                                 }
                             } else {
-                                err!(expr, UnsupportedOperation)
+                                return err!(expr, UnsupportedOperation)
                             }
                         },
-                    }
-                },
-                Expr_::Symbol(ref symbol) => {
-                    if let Some(value) = env.get(symbol).map(Clone::clone) {
-                        match value {
-                            Value::Function(function) => {
-                                let mut args = Vec::with_capacity(tail.len());
-
-                                for elem in tail {
-                                    args.push(try!(::eval::expr(elem, source, env)));
-                                }
+                    },
+                    _ => {
+                        let function = match try!(::eval::expr(head, &env, map, interner)) {
+                            Value::Function(function) => function,
+                            _ => return err!(head, ExpectedFunction),
+                        };
 
-                                if let Some(value) = function(&args) {
-                                    Ok(value)
-                                } else {
-                                    err!(expr, UnsupportedOperation)
+                        if let Function::Macro(ref macro_) = function {
+                            let mut args = Vec::with_capacity(tail.len());
+
+                            for arg in tail {
+                                args.push(try!(quote(arg, interner)));
+                            }
+
+                            let expansion = try!(expand(macro_, args, expr.span, map, interner));
+
+                            // NB the expansion gets evaluated in place of the macro call
+                            return match expr_(&expansion, &env, map, interner) {
+                                Err(error) => Err(in_expansion(error, expr.span)),
+                                Ok(tail) => Ok(tail),
+                            }
+                        }
+
+                        let mut args = Vec::with_capacity(tail.len());
+
+                        for elem in tail {
+                            args.push(try!(::eval::expr(elem, &env, map, interner)));
+                        }
+
+                        match function {
+                            Function::Builtin(builtin) => {
+                                let value = builtin(&args, &env, expr.span, map, interner);
+
+                                match value {
+                                    // NB point at the offending argument, if there's one
+                                    Err(failure) => {
+                                        return Err(failure.at(expr.span, |arg| tail[arg].span))
+                                    },
+                                    Ok(value) => value,
                                 }
                             },
-                            _ => err!(head, ExpectedFunction),
-
+                            Function::Lambda(lambda) => {
+                                match lambda.bind(args) {
+                                    Err(error) => return Err(Spanned::new(expr.span, error)),
+                                    Ok(env) => return Ok(Tail::Call(lambda, env)),
+                                }
+                            },
+                            // NB handled above
+                            Function::Macro(_) => unreachable!(),
                         }
+                    },
+                },
+            },
+            Expr_::Map(ref exprs) => {
+                let mut entries = HashMap::with_capacity(exprs.len() / 2);
+
+                for pair in exprs.chunks(2) {
+                    if let [ref key, ref value] = pair {
+                        let key = try!(::eval::expr(key, &env, map, interner));
+                        let value = try!(::eval::expr(value, &env, map, interner));
+
+                        entries.insert(key, value);
                     } else {
-                        err!(head, UndefinedSymbol)
+                        // NB the parser guarantees that maps have an even number of elements
+                        unreachable!();
                     }
-                },
-                _ => err!(head, ExpectedSymbol)
+                }
+
+                Value::Map(entries)
+            },
+            Expr_::Nil => Value::Nil,
+            Expr_::String(ref string) => Value::String(string.clone()),
+            Expr_::Symbol(ref symbol) => {
+                if let Some(value) = env.get(symbol) {
+                    value
+                } else {
+                    return err!(expr, UndefinedSymbol)
+                }
+            },
+            Expr_::Value(ref value) => value.clone(),
+            Expr_::Vector(ref exprs) => {
+                let mut elems = Vec::with_capacity(exprs.len());
+
+                for expr in exprs {
+                    elems.push(try!(::eval::expr(expr, &env, map, interner)))
+                }
+
+                Value::Vector(elems, expr.span)
             },
+        };
+
+        return Ok(Tail::Value(value))
+    }
+}
+
+/// Returns the entries of `map` sorted by their formatted keys
+///
+/// `HashMap` iterates in an arbitrary order that changes from process to process, maps are
+/// formatted, and their keys and values are listed, in this order instead
+fn entries<'a>(
+    map: &'a HashMap<Value, Value>,
+    interner: &Interner,
+) -> Vec<(&'a Value, &'a Value)> {
+    let mut entries = map.iter().map(|(key, value)| {
+        (key.display(interner), key, value)
+    }).collect::<Vec<_>>();
+
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    entries.into_iter().map(|(_, key, value)| (key, value)).collect()
+}
+
+/// Replaces the spans carried by `value` with the dummy span
+///
+/// Used for values whose spans point into source code other than the one being evaluated
+fn forget_spans(value: Value) -> Value {
+    fn seq(elems: Vec<Value>) -> Vec<Value> {
+        elems.into_iter().map(forget_spans).collect()
+    }
+
+    match value {
+        Value::List(elems, _) => Value::List(seq(elems), Span::dummy()),
+        Value::Map(map) => {
+            Value::Map(map.into_iter().map(|(k, v)| (forget_spans(k), forget_spans(v))).collect())
         },
-        Expr_::Nil => Ok(Value::Nil),
-        Expr_::String => Ok(Value::String(String::from_str(&source[expr.span]))),
-        Expr_::Symbol(ref symbol) => {
-            if let Some(value) = env.get(symbol) {
-                Ok(value.clone())
+        Value::Symbol(name, _) => Value::Symbol(name, Span::dummy()),
+        Value::Vector(elems, _) => Value::Vector(seq(elems), Span::dummy()),
+        value => value,
+    }
+}
+
+/// Expands a macro call, `args` are the unevaluated arguments of the call
+fn expand(
+    macro_: &Lambda,
+    args: Vec<Value>,
+    span: Span,
+    map: &mut SourceMap,
+    interner: &mut Interner,
+) -> Result<Expr, Error> {
+    apply(macro_, args, span, map, interner).map(|form| {
+        to_expr(&form, span, interner)
+    }).map_err(|error| {
+        in_expansion(error, span)
+    })
+}
+
+/// Marks an error as coming from the expansion of the macro call at `span`
+fn in_expansion(error: Error, span: Span) -> Error {
+    let node = match error.node {
+        // NB the outermost macro call is the one that gets reported
+        Error_::MacroExpansion(error) => Error_::MacroExpansion(error),
+        error => Error_::MacroExpansion(Box::new(error)),
+    };
+
+    Spanned::new(span, node)
+}
+
+/// Expands `form` once if it's a macro call, otherwise returns `None`
+fn macroexpand_1(
+    form: &Value,
+    env: &Env,
+    span: Span,
+    map: &mut SourceMap,
+    interner: &mut Interner,
+) -> Result<Option<Value>, Error> {
+    if let Value::List(ref elems, _) = *form {
+        if let Some(&Value::Symbol(name, _)) = elems.first() {
+            if let Some(Value::Function(Function::Macro(macro_))) = env.get(&name) {
+                let args = elems[1..].to_vec();
+                let form = apply(&macro_, args, span, map, interner);
+
+                return form.map(Some).map_err(|error| in_expansion(error, span))
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Evaluates a quasiquoted expression
+fn quasiquote(
+    expr: &Expr,
+    env: &Env,
+    map: &mut SourceMap,
+    interner: &mut Interner,
+) -> Result<Value, Error> {
+    /// Returns the argument of `(unquote ..)` or `(splice-unquote ..)`
+    fn unquoted(expr: &Expr, operator: Operator) -> Option<&Expr> {
+        match expr.node {
+            Expr_::List(ref exprs) => match &exprs[..] {
+                [ref head, ref arg] => match head.node {
+                    Expr_::Operator(op) if op == operator => Some(arg),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn seq(
+        exprs: &[Expr],
+        env: &Env,
+        map: &mut SourceMap,
+        interner: &mut Interner,
+    ) -> Result<Vec<Value>, Error> {
+        let mut elems = Vec::with_capacity(exprs.len());
+
+        for expr in exprs {
+            if let Some(arg) = unquoted(expr, Operator::SpliceUnquote) {
+                match try!(::eval::expr(arg, env, map, interner)) {
+                    Value::List(values, _) | Value::Vector(values, _) => elems.extend(values),
+                    _ => return Err(Spanned::new(expr.span, Error_::UnsupportedOperation)),
+                }
             } else {
-                err!(expr, UndefinedSymbol)
+                elems.push(try!(quasiquote(expr, env, map, interner)))
             }
+        }
+
+        Ok(elems)
+    }
+
+    if let Some(arg) = unquoted(expr, Operator::Unquote) {
+        return ::eval::expr(arg, env, map, interner)
+    }
+
+    match expr.node {
+        Expr_::List(ref exprs) => {
+            Ok(Value::List(try!(seq(exprs, env, map, interner)), expr.span))
         },
         Expr_::Vector(ref exprs) => {
-            let mut elems = Vec::with_capacity(exprs.len());
+            Ok(Value::Vector(try!(seq(exprs, env, map, interner)), expr.span))
+        },
+        _ => quote(expr, interner),
+    }
+}
+
+/// Converts an expression into a value without evaluating it
+fn quote(expr: &Expr, interner: &mut Interner) -> Result<Value, Error> {
+    fn seq(exprs: &[Expr], interner: &mut Interner) -> Result<Vec<Value>, Error> {
+        let mut elems = Vec::with_capacity(exprs.len());
 
-            for expr in exprs {
-                elems.push(try!(::eval::expr(expr, source, env)))
+        for expr in exprs {
+            elems.push(try!(quote(expr, interner)))
+        }
+
+        Ok(elems)
+    }
+
+    let value = match expr.node {
+        Expr_::BigInt(ref integer) => Value::BigInt(integer.clone()),
+        Expr_::Bool(bool) => Value::Bool(bool),
+        // NB only `parse::recover` produces error nodes
+        Expr_::Error => return Err(Spanned::new(expr.span, Error_::InvalidSyntax)),
+        Expr_::Float(float) => Value::Float(float),
+        Expr_::Integer(integer) => Value::Integer(integer),
+        Expr_::Keyword(name) => Value::Keyword(name),
+        Expr_::List(ref exprs) => Value::List(try!(seq(exprs, interner)), expr.span),
+        Expr_::Map(ref exprs) => {
+            let mut map = HashMap::with_capacity(exprs.len() / 2);
+
+            for pair in exprs.chunks(2) {
+                map.insert(try!(quote(&pair[0], interner)), try!(quote(&pair[1], interner)));
             }
 
-            Ok(Value::Vector(elems))
+            Value::Map(map)
         },
+        Expr_::Nil => Value::Nil,
+        Expr_::Operator(operator) => Value::Symbol(interner.intern(operator.as_str()), expr.span),
+        Expr_::String(ref string) => Value::String(string.clone()),
+        Expr_::Symbol(name) => Value::Symbol(name, expr.span),
+        Expr_::Value(ref value) => value.clone(),
+        Expr_::Vector(ref exprs) => Value::Vector(try!(seq(exprs, interner)), expr.span),
+    };
+
+    Ok(value)
+}
+
+/// Converts a value back into an expression, this is the inverse of `quote`
+///
+/// Values that carry the dummy span, because they were created at runtime, get `span` instead
+fn to_expr(value: &Value, span: Span, interner: &Interner) -> Expr {
+    fn seq(elems: &[Value], span: Span, interner: &Interner) -> Vec<Expr> {
+        elems.iter().map(|elem| to_expr(elem, span, interner)).collect()
     }
+
+    let span = match *value {
+        Value::List(_, own) | Value::Symbol(_, own) | Value::Vector(_, own) if !own.is_dummy() => {
+            own
+        },
+        _ => span,
+    };
+
+    let node = match *value {
+        // NB atoms and functions have no syntax, so they are embedded as they are
+        Value::Atom(_) | Value::Function(_) => Expr_::Value(value.clone()),
+        Value::BigInt(ref integer) => Expr_::BigInt(integer.clone()),
+        Value::Bool(bool) => Expr_::Bool(bool),
+        Value::Float(float) => Expr_::Float(float),
+        Value::Integer(integer) => Expr_::Integer(integer),
+        Value::Keyword(name) => Expr_::Keyword(name),
+        Value::List(ref elems, _) => {
+            let mut exprs = seq(elems, span, interner);
+
+            // NB like in the parser, special operators are only recognized in head position
+            if let Some(&Value::Symbol(name, _)) = elems.first() {
+                if let Some(operator) = Operator::from_str(&interner.get(&name)) {
+                    exprs[0].node = Expr_::Operator(operator);
+                }
+            }
+
+            Expr_::List(exprs)
+        },
+        Value::Map(ref map) => {
+            let mut exprs = Vec::with_capacity(2 * map.len());
+
+            for (key, value) in map {
+                exprs.push(to_expr(key, span, interner));
+                exprs.push(to_expr(value, span, interner));
+            }
+
+            Expr_::Map(exprs)
+        },
+        Value::Nil => Expr_::Nil,
+        Value::String(ref string) => Expr_::String(string.clone()),
+        Value::Symbol(name, _) => Expr_::Symbol(name),
+        Value::Vector(ref elems, _) => Expr_::Vector(seq(elems, span, interner)),
+    };
+
+    Spanned::new(span, node)
 }
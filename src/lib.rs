@@ -12,5 +12,6 @@ extern crate unicode_width;
 
 pub mod diagnostics;
 pub mod eval;
+pub mod repl;
 pub mod syntax;
 pub mod util;
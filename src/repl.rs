@@ -0,0 +1,88 @@
+//! Line-oriented input for the REPLs
+
+use std::mem;
+
+use syntax::Error_;
+use syntax::codemap::Source;
+use syntax::parse::lexer::{Delim, Lexer, Token_};
+
+/// Accumulates lines of input until they form complete forms, so a form can span several lines
+///
+/// Only the lexer looks at the input, the input gets parsed once it's complete
+pub struct Input {
+    buffer: String,
+    /// Everything before this byte position has been lexed
+    checked: usize,
+    /// The delimiters, in `buffer[..checked]`, that are still open
+    open: Vec<Delim>,
+}
+
+impl Input {
+    /// Creates an empty input
+    pub fn new() -> Input {
+        Input {
+            buffer: String::new(),
+            checked: 0,
+            open: vec![],
+        }
+    }
+
+    /// Appends a line to the input
+    ///
+    /// Returns the input accumulated so far if it's complete, otherwise returns `None` and the
+    /// input continues in the next line. The input is incomplete if a delimiter or a string is
+    /// still open at the end of the line. Syntax errors complete the input, so that the parser can
+    /// report them
+    pub fn push_line(&mut self, line: &str) -> Option<String> {
+        self.buffer.push_str(line);
+        self.buffer.push('\n');
+
+        let start = self.checked;
+
+        // NB every line ends with a newline, so no token other than a string continues in the
+        // next line. Lexing resumes at the end of the line, or at the start of an unterminated
+        // string
+        self.checked = self.buffer.len();
+
+        // NB `None` if the lexer doesn't run into an error or into an unbalanced delimiter
+        let mut is_complete = None;
+
+        for token in Lexer::new(Source::new(&self.buffer[start..])) {
+            match token {
+                Err(error) => {
+                    if error.node == Error_::UnterminatedString {
+                        self.checked = start + error.span.lo;
+                        is_complete = Some(false);
+                    } else {
+                        is_complete = Some(true);
+                    }
+
+                    break
+                },
+                Ok(token) => match token.node {
+                    Token_::Close(delim) => if self.open.pop() != Some(delim) {
+                        is_complete = Some(true);
+
+                        break
+                    },
+                    Token_::Open(delim) => self.open.push(delim),
+                    _ => {},
+                },
+            }
+        }
+
+        if is_complete.unwrap_or(self.open.is_empty()) {
+            Some(self.take())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the accumulated input and starts over
+    fn take(&mut self) -> String {
+        self.checked = 0;
+        self.open.clear();
+
+        mem::replace(&mut self.buffer, String::new())
+    }
+}
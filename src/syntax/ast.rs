@@ -1,52 +1,118 @@
 //! Abstract Syntax Tree
 
+use eval::Value;
 use syntax::codemap::Spanned;
+use util::bigint::BigInt;
 use util::interner::Name;
 
 /// A spanned expression
 pub type Expr = Spanned<Expr_>;
 
 /// An expression
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Expr_ {
+    /// `9223372036854775808`, an integer that doesn't fit in an `i64`
+    BigInt(BigInt),
     /// `true` or `false`
     Bool(bool),
+    /// An expression that couldn't be parsed, only produced by `parse::recover`
+    Error,
+    /// `1.5`, `1e-3`
+    Float(f64),
     /// `123`
     Integer(i64),
     /// `:a`
     Keyword(Name),
     /// `(+ 1 2)`
     List(Vec<Expr>),
+    /// `{:a 1 "b" 2}`, stored as a flat sequence of keys and values
+    Map(Vec<Expr>),
     /// `nil`
     Nil,
-    /// `def!`, `let*`
+    /// `def!`, `fn*`, `let*`
     Operator(Operator),
-    /// `"Hello, world!"`
-    String,
+    /// `"Hello, world!"`, stored with its escape sequences decoded
+    String(String),
     /// `+`, `-`
     Symbol(Name),
+    /// A value that has no syntax, e.g. a function in the expansion of a macro. Never produced by
+    /// the parser
+    Value(Value),
     /// `[1 "two" 3]`
     Vector(Vec<Expr>),
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 /// Special operators
 pub enum Operator {
+    /// `catch*`
+    Catch,
     /// `def!`
     Def,
+    /// `defmacro!`
+    Defmacro,
+    /// `do`
+    Do,
+    /// `fn*`
+    Fn,
     /// `if`
     If,
     /// `let*`
     Let,
+    /// `macroexpand`
+    Macroexpand,
+    /// `macroexpand-1`
+    Macroexpand1,
+    /// `quasiquote`, or the `` ` `` reader macro
+    Quasiquote,
+    /// `quote`, or the `'` reader macro
+    Quote,
+    /// `splice-unquote`, or the `~@` reader macro
+    SpliceUnquote,
+    /// `try*`
+    Try,
+    /// `unquote`, or the `~` reader macro
+    Unquote,
 }
 
 impl Operator {
+    /// Returns the name of the operator
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Operator::Catch => "catch*",
+            Operator::Def => "def!",
+            Operator::Defmacro => "defmacro!",
+            Operator::Do => "do",
+            Operator::Fn => "fn*",
+            Operator::If => "if",
+            Operator::Let => "let*",
+            Operator::Macroexpand => "macroexpand",
+            Operator::Macroexpand1 => "macroexpand-1",
+            Operator::Quasiquote => "quasiquote",
+            Operator::Quote => "quote",
+            Operator::SpliceUnquote => "splice-unquote",
+            Operator::Try => "try*",
+            Operator::Unquote => "unquote",
+        }
+    }
+
     /// Checks if `str` is a special operator
     pub fn from_str(str: &str) -> Option<Operator> {
         match str {
+            "catch*" => Some(Operator::Catch),
             "def!" => Some(Operator::Def),
+            "defmacro!" => Some(Operator::Defmacro),
+            "do" => Some(Operator::Do),
+            "fn*" => Some(Operator::Fn),
             "if" => Some(Operator::If),
             "let*" => Some(Operator::Let),
+            "macroexpand" => Some(Operator::Macroexpand),
+            "macroexpand-1" => Some(Operator::Macroexpand1),
+            "quasiquote" => Some(Operator::Quasiquote),
+            "quote" => Some(Operator::Quote),
+            "splice-unquote" => Some(Operator::SpliceUnquote),
+            "try*" => Some(Operator::Try),
+            "unquote" => Some(Operator::Unquote),
             _ => None,
         }
     }
@@ -1,41 +1,168 @@
 //! Maps spans to source code
 
-use std::mem;
 use std::ops::Index;
+use std::usize;
 
 /// Byte position
 pub type BytePos = usize;
 
-/// Source code
-pub struct Source(str);
+/// Identifies a file of a `SourceMap`
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct FileId(usize);
+
+impl FileId {
+    /// The file of source code that doesn't belong to any `SourceMap`, see `Source::new`
+    pub fn anonymous() -> FileId {
+        FileId(usize::MAX)
+    }
+}
+
+/// A named source file
+pub struct File {
+    /// Byte position of the start of each line
+    lines: Vec<BytePos>,
+    name: String,
+    text: String,
+}
+
+impl File {
+    /// Creates a file, the file is anonymous unless it gets added to a `SourceMap`
+    pub fn new(name: String, text: String) -> File {
+        let mut lines = vec![0];
+        lines.extend(text.char_indices().filter(|&(_, c)| c == '\n').map(|(i, _)| i + 1));
 
-impl Source {
-    /// Treats the input string as source code
-    pub fn new(input: &str) -> &Source {
-        unsafe {
-            mem::transmute(input)
+        File {
+            lines: lines,
+            name: name,
+            text: text,
         }
     }
 
-    /// Views the source code as a string slice
+    /// Returns the contents of the file
     pub fn as_str(&self) -> &str {
-        unsafe {
-            mem::transmute(self)
+        &self.text
+    }
+
+    /// Returns the contents of the line `line` (one-based) without its line terminator
+    pub fn line(&self, line: usize) -> &str {
+        let lo = self.lines[line - 1];
+        let hi = self.lines.get(line).map_or(self.text.len(), |&hi| hi);
+
+        self.text[lo..hi].trim_right_matches('\n').trim_right_matches('\r')
+    }
+
+    /// Maps a byte position to a line and a column
+    pub fn location(&self, pos: BytePos) -> Location {
+        // NB the last line that starts at or before `pos`
+        let line = match self.lines.binary_search(&pos) {
+            Err(i) => i,
+            Ok(i) => i + 1,
+        };
+        let lo = self.lines[line - 1];
+
+        Location {
+            col: self.text[lo..pos].chars().count() + 1,
+            line: line,
+        }
+    }
+
+    /// Returns the name of the file, e.g. its path
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// A line and a column, both one-based. Columns are counted in characters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Location {
+    /// Column
+    pub col: usize,
+    /// Line
+    pub line: usize,
+}
+
+/// Source code
+///
+/// A view into the text of a file that remembers which file it belongs to, so the spans produced
+/// by the parser can be mapped back to the file
+#[derive(Clone, Copy)]
+pub struct Source<'a> {
+    file: FileId,
+    text: &'a str,
+}
+
+impl<'a> Source<'a> {
+    /// Treats the input string as the source code of an anonymous file
+    pub fn new(input: &'a str) -> Source<'a> {
+        Source {
+            file: FileId::anonymous(),
+            text: input,
         }
     }
+
+    /// Views the source code as a string slice
+    pub fn as_str(&self) -> &'a str {
+        self.text
+    }
+
+    /// Returns the file this source code belongs to
+    pub fn file(&self) -> FileId {
+        self.file
+    }
 }
 
-impl Index<Span> for Source {
+impl<'a> Index<Span> for Source<'a> {
     type Output = str;
 
     fn index(&self, span: Span) -> &str {
-        &self.0[span.lo..span.hi]
+        &self.text[span.lo..span.hi]
+    }
+}
+
+/// A collection of source files, e.g. the files loaded by a program
+pub struct SourceMap {
+    files: Vec<File>,
+}
+
+impl SourceMap {
+    /// Creates an empty source map
+    pub fn new() -> SourceMap {
+        SourceMap {
+            files: vec![],
+        }
+    }
+
+    /// Adds a file to the map, and returns the id that the spans of its source code will carry
+    pub fn add(&mut self, name: String, text: String) -> FileId {
+        self.files.push(File::new(name, text));
+
+        FileId(self.files.len() - 1)
+    }
+
+    /// Retrieves a file, returns `None` if the file doesn't belong to this map, e.g. if it's
+    /// anonymous
+    pub fn get(&self, file: FileId) -> Option<&File> {
+        self.files.get(file.0)
+    }
+
+    /// Returns the source code of a file of this map
+    ///
+    /// # Panics
+    ///
+    /// If the file doesn't belong to this map
+    pub fn source(&self, file: FileId) -> Source {
+        Source {
+            file: file,
+            text: &self.files[file.0].text,
+        }
     }
 }
 
 /// Source code span
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Span {
+    /// The file that contains the span
+    pub file: FileId,
     /// Start of the span
     pub lo: BytePos,
     /// End of the span
@@ -43,11 +170,12 @@ pub struct Span {
 }
 
 impl Span {
-    /// Creates a new span from `lo` to `hi`
-    pub fn new(lo: BytePos, hi: BytePos) -> Span {
+    /// Creates a new span from `lo` to `hi` in `file`
+    pub fn new(file: FileId, lo: BytePos, hi: BytePos) -> Span {
         Span {
-            lo: lo,
+            file: file,
             hi: hi,
+            lo: lo,
         }
     }
 }
@@ -56,14 +184,22 @@ impl Span {
     /// The "dummy" span, should never be used for indexing
     pub fn dummy() -> Span {
         Span {
-            lo: 0,
+            file: FileId::anonymous(),
             hi: 0,
+            lo: 0,
         }
     }
+
+    /// Checks if this is the dummy span
+    ///
+    /// NB the empty span at the start of a file of a `SourceMap` is not the dummy span
+    pub fn is_dummy(&self) -> bool {
+        self.file == FileId::anonymous() && self.lo == 0 && self.hi == 0
+    }
 }
 
 /// A spanned node
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Spanned<T> {
     /// The node
     pub node: T,
@@ -0,0 +1,69 @@
+//! Concrete Syntax Tree
+//!
+//! Unlike the AST, the CST keeps every token of the source code, including whitespace, comments
+//! and the tokens that couldn't be lexed, so the source code can be reproduced byte-for-byte
+
+use syntax::Error_;
+use syntax::codemap::{Source, Spanned};
+use syntax::parse::lexer::{Delim, Token, Token_};
+
+/// A spanned node
+pub type Node = Spanned<Node_>;
+
+/// A node of the concrete syntax tree
+#[derive(Clone, Debug)]
+pub enum Node_ {
+    /// `"Hello`, a token that couldn't be lexed
+    Error(Error_),
+    /// `' a`, a reader macro followed by the whitespace and comments that precede its operand, and
+    /// then the operand. The operand is missing if the input or the enclosing sequence ends first
+    ReaderMacro(Token, Vec<Node>),
+    /// `(+ 1 2)`, the open delimiter, the nodes between the delimiters, and the close delimiter,
+    /// which is `None` if the sequence is unclosed. The close delimiter may not match the open
+    /// one, e.g. `(+ 1 2]`
+    Seq(Spanned<Delim>, Vec<Node>, Option<Spanned<Delim>>),
+    /// `123`, `:a`, `; comment`, an atom or trivia, or a close delimiter that doesn't close any
+    /// sequence
+    Token(Token_),
+}
+
+impl Node_ {
+    /// Is this node trivia, i.e. whitespace or a comment?
+    pub fn is_trivia(&self) -> bool {
+        match *self {
+            Node_::Token(Token_::Comment) | Node_::Token(Token_::Whitespace) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Reproduces the source code of the `nodes`, token by token
+pub fn text(nodes: &[Node], source: Source) -> String {
+    fn node(string: &mut String, node: &Node, source: Source) {
+        match node.node {
+            Node_::Error(_) | Node_::Token(_) => string.push_str(&source[node.span]),
+            Node_::ReaderMacro(token, ref nodes) => {
+                string.push_str(&source[token.span]);
+                seq(string, nodes, source);
+            },
+            Node_::Seq(open, ref nodes, close) => {
+                string.push_str(&source[open.span]);
+                seq(string, nodes, source);
+
+                if let Some(close) = close {
+                    string.push_str(&source[close.span]);
+                }
+            },
+        }
+    }
+
+    fn seq(string: &mut String, nodes: &[Node], source: Source) {
+        for n in nodes {
+            node(string, n, source)
+        }
+    }
+
+    let mut string = String::new();
+    seq(&mut string, nodes, source);
+    string
+}
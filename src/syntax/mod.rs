@@ -2,6 +2,7 @@
 
 pub mod ast;
 pub mod codemap;
+pub mod cst;
 pub mod parse;
 pub mod pp;
 
@@ -11,20 +12,30 @@ use syntax::codemap::Spanned;
 pub type Error = Spanned<Error_>;
 
 /// Syntax error
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Error_ {
-    /// Only a single expression is expected per line. `(+ 1 2) 3` is an error
-    ExpectedEndOfLine,
     /// ':'
     EmptyKeyword,
+    /// Only a single expression is expected per line. `(+ 1 2) 3` is an error
+    ExpectedEndOfLine,
+    /// `{:a 1 :b}`
+    ExpectedMapValue,
     /// `(+ 1 2]`
     IncorrectCloseDelimiter,
-    /// The integer literal doesn't fit in 64 bits
-    IntegerTooLarge,
+    /// `0b102`, `0xfg`, the digit is not valid in the base of the integer literal
+    InvalidDigit,
+    /// `1.2.3`, `123abc`, `0x`
+    InvalidNumber,
+    /// `"\u{110000}"`
+    InvalidUnicodeEscape,
     /// `(+ def! 1)`
     OperatorNotAllowedHere,
     /// `(+ 1 2`
     UnclosedDelimiter,
+    /// `(+ 1 2))`
+    UnexpectedCloseDelimiter,
+    /// The input is empty or contains only whitespace and comments
+    UnexpectedEndOfInput,
     /// `"\a"`
     UnknownCharacterEscape,
     /// No known token starts with this character
@@ -32,3 +43,25 @@ pub enum Error_ {
     /// `"Hello`
     UnterminatedString,
 }
+
+impl Error_ {
+    /// A short description of the error
+    pub fn description(&self) -> &'static str {
+        match *self {
+            Error_::EmptyKeyword => "empty keyword",
+            Error_::ExpectedEndOfLine => "expected end of line",
+            Error_::ExpectedMapValue => "expected a value for this key",
+            Error_::IncorrectCloseDelimiter => "incorrect close delimiter",
+            Error_::InvalidDigit => "invalid digit for the base of this literal",
+            Error_::InvalidNumber => "invalid numeric literal",
+            Error_::InvalidUnicodeEscape => "invalid unicode character escape",
+            Error_::OperatorNotAllowedHere => "keyword not allowed here",
+            Error_::UnclosedDelimiter => "un-closed delimiter",
+            Error_::UnexpectedCloseDelimiter => "unexpected close delimiter",
+            Error_::UnexpectedEndOfInput => "unexpected end of input",
+            Error_::UnknownCharacterEscape => "unknown character escape",
+            Error_::UnknownStartOfToken => "unknown start of token",
+            Error_::UnterminatedString => "unterminated string literal",
+        }
+    }
+}
@@ -4,11 +4,12 @@ use std::iter::Peekable;
 use std::str::CharIndices;
 
 use syntax::ast::Operator;
-use syntax::codemap::{BytePos, Source, Span, Spanned};
+use syntax::codemap::{BytePos, FileId, Source, Span, Spanned};
 use syntax::{Error, Error_};
 
 /// Lexer
 pub struct Lexer<'a> {
+    file: FileId,
     input: &'a str,
     iter: Peekable<CharIndices<'a>>,
     pos: BytePos,
@@ -16,10 +17,11 @@ pub struct Lexer<'a> {
 
 impl<'a> Lexer<'a> {
     /// Lexes the source code
-    pub fn new(source: &'a Source) -> Lexer<'a> {
+    pub fn new(source: Source<'a>) -> Lexer<'a> {
         let input = source.as_str();
 
         Lexer {
+            file: source.file(),
             input: input,
             iter: input.char_indices().peekable(),
             pos: 0,
@@ -38,18 +40,18 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    /// Raises an error
-    fn error(&mut self, lo: BytePos, error: Error_) -> Result<Token, Error> {
-        Err(self.spanned(lo, error))
-    }
-
-    /// Lexes an integer
-    fn integer(&mut self) -> Result<Token, Error> {
+    /// Lexes a comment. Current position must be `;`
+    fn comment(&mut self) -> Result<Token, Error> {
         let lo = self.pos;
 
-        self.advance_while(is_part_of_integer);
+        self.advance_while(|c| c != '\n');
 
-        Ok(self.spanned(lo, Token_::Integer))
+        Ok(self.spanned(lo, Token_::Comment))
+    }
+
+    /// Raises an error
+    fn error(&mut self, lo: BytePos, error: Error_) -> Result<Token, Error> {
+        Err(self.spanned(lo, error))
     }
 
     /// Lexes a keyword. Current position must be `:`
@@ -90,26 +92,36 @@ impl<'a> Lexer<'a> {
     fn spanned<T>(&mut self, lo: BytePos, node: T) -> Spanned<T> {
         Spanned {
             node: node,
-            span: Span { lo: lo, hi: self.next_byte_pos() },
+            span: Span::new(self.file, lo, self.next_byte_pos()),
         }
     }
 
-    /// Lexes a string
+    /// Lexes a number. Current position must be a digit, or a sign followed by a digit
+    ///
+    /// The number extends over all the characters that could be part of a symbol, e.g. `1.5e-3`
+    /// or `123abc`, the parser checks that it's a valid integer or float
+    fn number(&mut self) -> Result<Token, Error> {
+        let lo = self.pos;
+
+        self.advance_while(is_part_of_symbol);
+
+        Ok(self.spanned(lo, Token_::Number))
+    }
+
+    /// Lexes a string. Current position must be `"`
     fn string(&mut self) -> Result<Token, Error> {
         let lo = self.pos;
 
-        match self.next() {
-            None => self.error(lo, Error_::UnterminatedString),
-            Some(_) => {
-                self.advance_while(|c| c != '"');
-
-                // eat `"`
-                if self.next().is_none() {
-                    self.error(lo, Error_::UnterminatedString)
-                } else {
-                    Ok(self.spanned(lo, Token_::String))
-                }
-            },
+        loop {
+            match self.next() {
+                None => return self.error(lo, Error_::UnterminatedString),
+                Some('"') => return Ok(self.spanned(lo, Token_::String)),
+                Some('\\') => {
+                    // NB the escape sequence gets validated by the parser
+                    self.next();
+                },
+                Some(_) => {},
+            }
         }
     }
 
@@ -126,6 +138,19 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Lexes an unquote (`~`) or a splice-unquote (`~@`). Current position must be `~`
+    fn unquote(&mut self) -> Result<Token, Error> {
+        let lo = self.pos;
+
+        if self.iter.peek().map_or(false, |&(_, c)| c == '@') {
+            self.next();
+
+            Ok(self.spanned(lo, Token_::SpliceUnquote))
+        } else {
+            Ok(self.spanned(lo, Token_::Unquote))
+        }
+    }
+
     /// Lexes whitespace
     fn whitespace(&mut self) -> Result<Token, Error> {
         let lo = self.pos;
@@ -143,14 +168,23 @@ impl<'a> Iterator for Lexer<'a> {
         self.next().map(|c| {
             match c {
                 '"' => self.string(),
+                '\'' => self.token(Token_::Quote),
                 '(' => self.token(Token_::Open(Delim::Paren)),
                 ')' => self.token(Token_::Close(Delim::Paren)),
                 ':' => self.keyword(),
+                ';' => self.comment(),
+                '@' => self.token(Token_::Deref),
                 '[' => self.token(Token_::Open(Delim::Bracket)),
                 ']' => self.token(Token_::Close(Delim::Bracket)),
+                '`' => self.token(Token_::Quasiquote),
                 '{' => self.token(Token_::Open(Delim::Brace)),
                 '}' => self.token(Token_::Close(Delim::Brace)),
-                c if is_part_of_integer(c) => self.integer(),
+                '~' => self.unquote(),
+                // NB `-5` is a number, but `-` and `-foo` are symbols
+                '+' | '-' if self.iter.peek().map_or(false, |&(_, c)| is_digit(c)) => {
+                    self.number()
+                },
+                c if is_digit(c) => self.number(),
                 c if is_start_of_symbol(c) => self.symbol(),
                 c if is_whitespace(c) => self.whitespace(),
                 _ => {
@@ -163,26 +197,39 @@ impl<'a> Iterator for Lexer<'a> {
     }
 }
 
+/// A spanned token
 pub type Token = Spanned<Token_>;
 
 /// Tokens
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Token_ {
     /// Closing delimiter: `]`
     Close(Delim),
-    /// `123`
-    Integer,
+    /// `; Hello, world!`
+    Comment,
+    /// `@`
+    Deref,
     /// `:a`, `:1`
     Keyword,
+    /// `123`, `-1.5e3`
+    Number,
     /// Opening delimiter: `(`
     Open(Delim),
     /// `def!`, `let*`
     Operator(Operator),
+    /// `` ` ``
+    Quasiquote,
+    /// `'`
+    Quote,
+    /// `~@`
+    SpliceUnquote,
     /// `"Hello, world!"`
     String,
     /// `+`, `=`
     Symbol,
-    /// ` ` or `\t`
+    /// `~`
+    Unquote,
+    /// ` `, `\t` or `\n`
     Whitespace,
 }
 
@@ -205,8 +252,8 @@ fn is_delim(c: char) -> bool {
     }
 }
 
-/// Is this character a part of an integer?
-fn is_part_of_integer(c: char) -> bool {
+/// Is this character a decimal digit?
+fn is_digit(c: char) -> bool {
     match c {
         '0'...'9' => true,
         _ => false
@@ -216,7 +263,7 @@ fn is_part_of_integer(c: char) -> bool {
 /// Is this character a part of a symbol?
 fn is_part_of_symbol(c: char) -> bool {
     match c {
-        '"' | ';' | '\'' | '\\' | ':' => false,
+        '"' | ';' | '\'' | '\\' | ':' | '`' | '~' => false,
         c if is_delim(c) => false,
         c if is_whitespace(c) => false,
         _ => true,
@@ -225,13 +272,13 @@ fn is_part_of_symbol(c: char) -> bool {
 
 /// Is this character the start of a symbol?
 fn is_start_of_symbol(c: char) -> bool {
-    !is_part_of_integer(c) && is_part_of_symbol(c)
+    !is_digit(c) && is_part_of_symbol(c)
 }
 
 /// Is this character whitespace?
 fn is_whitespace(c: char) -> bool {
     match c {
-        ' ' | '\t' | ',' => true,
+        ' ' | '\t' | '\n' | '\r' | ',' => true,
         _ => false,
     }
 }
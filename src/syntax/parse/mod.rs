@@ -1,27 +1,41 @@
 //! Parser
 
-mod lexer;
+pub mod lexer;
 
+use std::char;
 use std::iter::Peekable;
 
-use syntax::ast::{Expr, Expr_};
-use syntax::codemap::{Source, Span, Spanned};
+use syntax::ast::{Expr, Expr_, Operator};
+use syntax::codemap::{BytePos, Source, Span, Spanned};
+use syntax::cst::{Node, Node_};
 use syntax::parse::lexer::{Delim, Lexer, Token_};
 use syntax::{Error, Error_};
+use util::bigint::BigInt;
 use util::interner::Interner;
 
+/// Parses the tokens of the source code, recovering from syntax errors. The strict parser only
+/// uses it to convert the nodes of a concrete syntax tree, see `lower`
 struct Parser<'a> {
+    /// Close delimiters of the sequences that are being parsed, innermost last
+    delims: Vec<Delim>,
+    /// Errors recorded while recovering
+    errors: Vec<Error>,
+    /// The number of errors recorded before the current top-level form
+    form_errors: usize,
     // NB `Option` needed for option dance
     interner: Option<&'a mut Interner>,
     lexer: Peekable<Lexer<'a>>,
-    source: &'a Source,
+    source: Source<'a>,
     span: Span,
 }
 
 impl<'a> Parser<'a> {
     /// Parses the source code
-    fn new(source: &'a Source, interner: &'a mut Interner) -> Parser<'a> {
+    fn new(source: Source<'a>, interner: &'a mut Interner) -> Parser<'a> {
         Parser {
+            delims: vec![],
+            errors: vec![],
+            form_errors: 0,
             interner: Some(interner),
             lexer: Lexer::new(source).peekable(),
             source: source,
@@ -29,30 +43,64 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Records an error, parsing continues after it
+    fn error(&mut self, error: Error) {
+        self.errors.push(error)
+    }
+
     /// Parses an expression
     fn expr(&mut self) -> Result<Expr, Error> {
         match self.next() {
-            None => unreachable!(),
-            Some(Ok(Token_::Integer)) => self.integer(),
+            None => {
+                let span = self.new_span(self.span.hi, self.span.hi);
+
+                Err(Spanned::new(span, Error_::UnexpectedEndOfInput))
+            },
+            Some(Ok(Token_::Comment)) => self.expr(),
+            Some(Ok(Token_::Deref)) => {
+                // NB `deref` is a function rather than a special operator
+                let deref = self.interner.as_mut().unwrap().intern("deref");
+
+                self.reader_macro(Expr_::Symbol(deref))
+            },
+            Some(Ok(Token_::Number)) => self.number(),
             Some(Ok(Token_::Keyword)) => self.keyword(),
             Some(Ok(Token_::Operator(_))) => Err(self.spanned(Error_::OperatorNotAllowedHere)),
+            Some(Ok(Token_::Quasiquote)) => {
+                self.reader_macro(Expr_::Operator(Operator::Quasiquote))
+            },
+            Some(Ok(Token_::Quote)) => self.reader_macro(Expr_::Operator(Operator::Quote)),
+            Some(Ok(Token_::SpliceUnquote)) => {
+                self.reader_macro(Expr_::Operator(Operator::SpliceUnquote))
+            },
+            Some(Ok(Token_::Unquote)) => self.reader_macro(Expr_::Operator(Operator::Unquote)),
             Some(Ok(Token_::String)) => self.string(),
             Some(Ok(Token_::Symbol)) => self.symbol(),
             Some(Ok(Token_::Whitespace)) => self.expr(),
             Some(Err(error)) => Err(self.spanned(error)),
             Some(Ok(Token_::Open(Delim::Paren))) => self.list(),
             Some(Ok(Token_::Open(Delim::Bracket))) => self.vector(),
-            _ => unimplemented!(),
+            Some(Ok(Token_::Open(Delim::Brace))) => self.map(),
+            Some(Ok(Token_::Close(_))) => Err(self.spanned(Error_::UnexpectedCloseDelimiter)),
         }
     }
 
-    /// Parses an integer
-    fn integer(&mut self) -> Result<Expr, Error> {
-        let span = self.span;
+    /// Parses an expression, an expression that can't be parsed is replaced with an error node
+    fn expr_or_error(&mut self) -> Expr {
+        match self.expr() {
+            Err(error) => {
+                self.error(error);
 
-        match self.source[span].parse() {
-            Err(_) => Err(self.spanned(Error_::IntegerTooLarge)),
-            Ok(integer) => Ok(self.spanned(Expr_::Integer(integer))),
+                // NB the error node spans the token that couldn't be parsed, e.g. the whole
+                // string rather than its invalid escape sequence
+                let span = match error.node {
+                    Error_::UnexpectedEndOfInput => error.span,
+                    _ => self.span,
+                };
+
+                Spanned::new(span, Expr_::Error)
+            },
+            Ok(expr) => expr,
         }
     }
 
@@ -70,7 +118,29 @@ impl<'a> Parser<'a> {
 
     /// Parses a list
     fn list(&mut self) -> Result<Expr, Error> {
-        Ok(try!(self.seq(Delim::Paren, true)).map(Expr_::List))
+        Ok(self.seq(Delim::Paren, true).map(Expr_::List))
+    }
+
+    /// Parses a map
+    fn map(&mut self) -> Result<Expr, Error> {
+        let mut map = self.seq(Delim::Brace, false);
+
+        if map.node.len() % 2 != 0 {
+            // NB the last key is the one that's missing a value
+            let span = map.node[map.node.len() - 1].span;
+
+            self.error(Spanned::new(span, Error_::ExpectedMapValue));
+
+            // NB the missing value becomes an error node
+            map.node.push(Spanned::new(self.new_span(span.hi, span.hi), Expr_::Error));
+        }
+
+        Ok(map.map(Expr_::Map))
+    }
+
+    /// Creates a span from `lo` to `hi` in the source code that's being parsed
+    fn new_span(&self, lo: BytePos, hi: BytePos) -> Span {
+        Span::new(self.source.file(), lo, hi)
     }
 
     /// Advances the parser by one token
@@ -89,57 +159,299 @@ impl<'a> Parser<'a> {
         })
     }
 
+    /// Converts a node of the concrete syntax tree, that's not trivia, into an expression
+    fn node(&mut self, node: &Node) -> Result<Expr, Error> {
+        match node.node {
+            Node_::Error(error) => Err(Spanned::new(node.span, error)),
+            Node_::ReaderMacro(token, ref nodes) => {
+                let head = match token.node {
+                    Token_::Deref => {
+                        // NB `deref` is a function rather than a special operator
+                        let deref = self.interner.as_mut().unwrap().intern("deref");
+
+                        Expr_::Symbol(deref)
+                    },
+                    Token_::Quasiquote => Expr_::Operator(Operator::Quasiquote),
+                    Token_::Quote => Expr_::Operator(Operator::Quote),
+                    Token_::SpliceUnquote => Expr_::Operator(Operator::SpliceUnquote),
+                    Token_::Unquote => Expr_::Operator(Operator::Unquote),
+                    _ => unreachable!(),
+                };
+                let head = Spanned::new(token.span, head);
+
+                let expr = match nodes.last() {
+                    Some(operand) if !operand.node.is_trivia() => match operand.node {
+                        // NB special operators can be quoted, e.g. `'if`, but only if there is
+                        // no trivia in between
+                        Node_::Token(Token_::Operator(operator)) if nodes.len() == 1 => {
+                            Spanned::new(operand.span, Expr_::Operator(operator))
+                        },
+                        _ => try!(self.node(operand)),
+                    },
+                    // NB the operand is missing
+                    _ => {
+                        let hi = node.span.hi;
+                        let rest = &self.source.as_str()[hi..];
+
+                        return if rest.starts_with(|c: char| ")]}".contains(c)) {
+                            let span = self.new_span(hi, hi + 1);
+
+                            Err(Spanned::new(span, Error_::UnexpectedCloseDelimiter))
+                        } else {
+                            let span = self.new_span(hi, hi);
+
+                            Err(Spanned::new(span, Error_::UnexpectedEndOfInput))
+                        }
+                    },
+                };
+
+                Ok(Spanned::new(node.span, Expr_::List(vec![head, expr])))
+            },
+            Node_::Seq(open, ref nodes, close) => {
+                let mut exprs = vec![];
+
+                for elem in nodes.iter().filter(|node| !node.node.is_trivia()) {
+                    let is_head = open.node == Delim::Paren && exprs.len() == 0;
+
+                    match elem.node {
+                        Node_::Token(Token_::Operator(operator)) if is_head => {
+                            exprs.push(Spanned::new(elem.span, Expr_::Operator(operator)))
+                        },
+                        _ => exprs.push(try!(self.node(elem))),
+                    }
+                }
+
+                match close {
+                    None => {
+                        let span = self.new_span(node.span.hi, node.span.hi);
+
+                        return Err(Spanned::new(span, Error_::UnclosedDelimiter))
+                    },
+                    Some(close) => if close.node != open.node {
+                        return Err(Spanned::new(close.span, Error_::IncorrectCloseDelimiter))
+                    },
+                }
+
+                match open.node {
+                    Delim::Brace => if exprs.len() % 2 == 0 {
+                        Ok(Spanned::new(node.span, Expr_::Map(exprs)))
+                    } else {
+                        // NB the last key is the one that's missing a value
+                        let span = exprs[exprs.len() - 1].span;
+
+                        Err(Spanned::new(span, Error_::ExpectedMapValue))
+                    },
+                    Delim::Bracket => Ok(Spanned::new(node.span, Expr_::Vector(exprs))),
+                    Delim::Paren => Ok(Spanned::new(node.span, Expr_::List(exprs))),
+                }
+            },
+            Node_::Token(token) => {
+                self.span = node.span;
+
+                match token {
+                    Token_::Close(_) => Err(self.spanned(Error_::UnexpectedCloseDelimiter)),
+                    Token_::Keyword => self.keyword(),
+                    Token_::Number => self.number(),
+                    Token_::Operator(_) => Err(self.spanned(Error_::OperatorNotAllowedHere)),
+                    Token_::String => self.string(),
+                    Token_::Symbol => self.symbol(),
+                    // NB the other tokens are either trivia or part of a reader macro or a
+                    // sequence
+                    _ => unreachable!(),
+                }
+            },
+        }
+    }
+
+    /// Parses a number: an integer, e.g. `-123`, `0xff` or `1_000_000`, or a float, e.g. `1.5e-3`
+    ///
+    /// Integers can have a `0x` (hexadecimal), `0o` (octal) or `0b` (binary) prefix after their
+    /// sign, and `_` can be used to separate their digits
+    fn number(&self) -> Result<Expr, Error> {
+        let text = &self.source[self.span];
+        let negative = text.starts_with("-");
+        // NB skip the sign
+        let start = if negative || text.starts_with("+") { 1 } else { 0 };
+
+        let (radix, start) = match &text[start..] {
+            s if s.starts_with("0b") => (2, start + 2),
+            s if s.starts_with("0o") => (8, start + 2),
+            s if s.starts_with("0x") => (16, start + 2),
+            s if s.chars().all(|c| c.is_digit(10) || c == '_') => (10, start),
+            // NB separators are also allowed in floats, e.g. `1_000.5`
+            _ => {
+                let float = text.chars().filter(|&c| c != '_').collect::<String>();
+
+                return match float.trim_left_matches('+').parse() {
+                    Err(_) => Err(self.spanned(Error_::InvalidNumber)),
+                    Ok(float) => Ok(self.spanned(Expr_::Float(float))),
+                }
+            },
+        };
+
+        let mut digits = String::with_capacity(text.len());
+
+        if negative {
+            digits.push('-');
+        }
+
+        for (i, c) in text[start..].char_indices() {
+            if c == '_' {
+                continue
+            } else if c.is_digit(radix) {
+                digits.push(c);
+            } else {
+                let lo = self.span.lo + start + i;
+                let span = self.new_span(lo, lo + c.len_utf8());
+
+                return Err(Spanned::new(span, Error_::InvalidDigit))
+            }
+        }
+
+        // NB e.g. `0x` or `0b_`
+        if digits.trim_left_matches('-').is_empty() {
+            return Err(self.spanned(Error_::InvalidNumber))
+        }
+
+        // NB integers that don't fit in an `i64` become big integers
+        match i64::from_str_radix(&digits, radix) {
+            Err(_) => {
+                let integer = BigInt::from_str_radix(&digits, radix).unwrap();
+
+                Ok(self.spanned(Expr_::BigInt(integer)))
+            },
+            Ok(integer) => Ok(self.spanned(Expr_::Integer(integer))),
+        }
+    }
+
+    /// Parses a reader macro, e.g. `'a`, into a list headed by `head`, e.g. `(quote a)`. Current
+    /// position must be the reader macro
+    fn reader_macro(&mut self, head: Expr_) -> Result<Expr, Error> {
+        let head = self.spanned(head);
+
+        // NB special operators can be quoted, e.g. `'if`
+        let expr = match self.lexer.peek() {
+            Some(&Ok(Spanned { node: Token_::Operator(operator), .. })) => {
+                self.next();
+
+                self.spanned(Expr_::Operator(operator))
+            },
+            // NB the close delimiter is left to the enclosing sequence
+            Some(&Ok(Spanned { node: Token_::Close(_), span })) => {
+                self.error(Spanned::new(span, Error_::UnexpectedCloseDelimiter));
+
+                Spanned::new(self.new_span(span.lo, span.lo), Expr_::Error)
+            },
+            _ => self.expr_or_error(),
+        };
+        let span = self.new_span(head.span.lo, expr.span.hi);
+
+        Ok(Spanned::new(span, Expr_::List(vec![head, expr])))
+    }
+
     /// Parses a "sequence" until the `close` delimiter is reached. Current position must be the
     /// open delimiter
     ///
     /// if `accept_operator` is true, then the first element of the sequence can be a special
     /// operator
-    fn seq(&mut self, close: Delim, accept_operator: bool) -> Result<Spanned<Vec<Expr>>, Error> {
-        let lo = self.span.lo;
+    ///
+    /// An unclosed sequence is closed at the end of the input or before the next open delimiter
+    /// that starts a line (i.e. the next top-level form), and an incorrect close delimiter closes
+    /// the sequence. The close delimiter is only consumed if it doesn't belong to an enclosing
+    /// sequence. See `resyncs` for when an open delimiter that starts a line closes the sequence
+    fn seq(&mut self, close: Delim, accept_operator: bool) -> Spanned<Vec<Expr>> {
+        let open = self.span;
         let mut exprs = vec![];
 
+        self.delims.push(close);
+
         loop {
             match self.lexer.peek() {
                 None => {
-                    let span = Span::new(self.span.hi, self.span.hi);
+                    self.error(Spanned::new(open, Error_::UnclosedDelimiter));
 
-                    return Err(Spanned::new(span, Error_::UnclosedDelimiter))
-                },
-                Some(&Err(error)) => {
-                    self.next();
-
-                    return Err(error)
+                    break
                 },
+                Some(&Err(_)) => exprs.push(self.expr_or_error()),
                 Some(&Ok(token)) => {
                     match token.node {
                         Token_::Close(delim) => {
-                            self.next();
+                            // NB leave the delimiter to the enclosing sequence it belongs to
+                            let is_enclosing = delim != close && self.delims.contains(&delim);
+
+                            if !is_enclosing {
+                                self.next();
+                            }
 
-                            if delim == close {
-                                break
-                            } else {
-                                return Err(self.spanned(Error_::IncorrectCloseDelimiter))
+                            if delim != close {
+                                let error = Error_::IncorrectCloseDelimiter;
+
+                                self.error(Spanned::new(token.span, error));
                             }
+
+                            break
+                        },
+                        Token_::Open(_) if self.resyncs(token.span) => {
+                            self.error(Spanned::new(open, Error_::UnclosedDelimiter));
+
+                            break
                         },
                         Token_::Operator(operator) if accept_operator && exprs.len() == 0 => {
                             self.next();
 
                             exprs.push(self.spanned(Expr_::Operator(operator)));
                         },
-                        Token_::Whitespace => {
+                        Token_::Comment | Token_::Whitespace => {
                             self.next();
                         },
                         _ => {
-                            exprs.push(try!(self.expr()))
+                            exprs.push(self.expr_or_error())
                         }
                     }
                 }
             }
         }
 
-        let span = Span::new(lo, self.span.hi);
+        self.delims.pop();
+
+        let span = self.new_span(open.lo, self.span.hi);
+
+        Spanned::new(span, exprs)
+    }
+
+    /// Checks if the unclosed sequences should be closed before the open delimiter at `span`, i.e.
+    /// if the delimiter starts the next top-level form
+    ///
+    /// That's only the case if the delimiter starts a line, and either the current top-level form
+    /// has errors or the input ends before the form is closed. Otherwise, the delimiter belongs to
+    /// valid code that's just not indented, e.g. `(def! f\n(fn* [] 1))`
+    fn resyncs(&self, span: Span) -> bool {
+        if !self.starts_line(span) {
+            return false
+        }
+
+        if self.errors.len() > self.form_errors {
+            return true
+        }
+
+        // NB look for the close delimiters of the sequences that are open, without parsing
+        let mut depth = self.delims.len();
+
+        for token in Lexer::new(Source::new(&self.source.as_str()[span.lo..])) {
+            match token {
+                Ok(Spanned { node: Token_::Close(_), .. }) => {
+                    depth -= 1;
+
+                    if depth == 0 {
+                        return false
+                    }
+                },
+                Ok(Spanned { node: Token_::Open(_), .. }) => depth += 1,
+                _ => {},
+            }
+        }
 
-        Ok(Spanned::new(span, exprs))
+        true
     }
 
     fn spanned<T>(&self, node: T) -> Spanned<T> {
@@ -149,9 +461,74 @@ impl<'a> Parser<'a> {
         }
     }
 
-    /// Parses a string
+    /// Checks if `span` is at the start of a line
+    fn starts_line(&self, span: Span) -> bool {
+        self.source.as_str()[..span.lo].ends_with("\n")
+    }
+
+    /// Parses a string, decoding its escape sequences
     fn string(&self) -> Result<Expr, Error> {
-        Ok(self.spanned(Expr_::String))
+        // NB skip the surrounding quotes
+        let lo = self.span.lo + 1;
+        let literal = &self.source[self.new_span(lo, self.span.hi - 1)];
+        let mut string = String::with_capacity(literal.len());
+        let mut chars = literal.char_indices().peekable();
+
+        while let Some((start, c)) = chars.next() {
+            if c != '\\' {
+                string.push(c);
+                continue
+            }
+
+            // NB the lexer guarantees that a backslash is always followed by another character
+            let (mut end, escape) = chars.next().unwrap();
+            end += escape.len_utf8();
+
+            let c = match escape {
+                '"' => '"',
+                '\\' => '\\',
+                'n' => '\n',
+                't' => '\t',
+                'u' => {
+                    let rest = &literal[end..];
+                    let mut c = None;
+
+                    if rest.starts_with("{") {
+                        if let Some(close) = rest.find('}') {
+                            let digits = &rest[1..close];
+
+                            end += close + 1;
+
+                            if !digits.is_empty() && digits.len() <= 6 {
+                                c = u32::from_str_radix(digits, 16).ok().and_then(char::from_u32);
+                            }
+                        }
+                    }
+
+                    while chars.peek().map_or(false, |&(i, _)| i < end) {
+                        chars.next();
+                    }
+
+                    match c {
+                        None => {
+                            let span = self.new_span(lo + start, lo + end);
+
+                            return Err(Spanned::new(span, Error_::InvalidUnicodeEscape))
+                        },
+                        Some(c) => c,
+                    }
+                },
+                _ => {
+                    let span = self.new_span(lo + start, lo + end);
+
+                    return Err(Spanned::new(span, Error_::UnknownCharacterEscape))
+                },
+            };
+
+            string.push(c);
+        }
+
+        Ok(self.spanned(Expr_::String(string)))
     }
 
     /// Parses a symbol
@@ -175,27 +552,162 @@ impl<'a> Parser<'a> {
 
     /// Parses a vector
     fn vector(&mut self) -> Result<Expr, Error> {
-        Ok(try!(self.seq(Delim::Bracket, false)).map(Expr_::Vector))
+        Ok(self.seq(Delim::Bracket, false).map(Expr_::Vector))
     }
 }
 
 /// Parses a single expression
-pub fn expr<'a>(source: &'a Source, interner: &'a mut Interner) -> Result<Expr, Error> {
+pub fn expr<'a>(source: Source<'a>, interner: &'a mut Interner) -> Result<Expr, Error> {
+    let nodes = cst(source);
+    let mut forms = nodes.iter().filter(|node| !node.node.is_trivia());
+
+    let expr = match forms.next() {
+        None => {
+            let end = source.as_str().len();
+            let span = Span::new(source.file(), end, end);
+
+            return Err(Spanned::new(span, Error_::UnexpectedEndOfInput))
+        },
+        Some(form) => try!(Parser::new(source, interner).node(form)),
+    };
+
+    match forms.next() {
+        None => Ok(expr),
+        Some(form) => {
+            // NB point at the first token of the next form
+            let span = match form.node {
+                Node_::ReaderMacro(token, _) => token.span,
+                Node_::Seq(open, _, _) => open.span,
+                _ => form.span,
+            };
+
+            Err(Spanned::new(span, Error_::ExpectedEndOfLine))
+        },
+    }
+}
+
+/// Parses all the expressions in the source code
+pub fn exprs<'a>(source: Source<'a>, interner: &'a mut Interner) -> Result<Vec<Expr>, Error> {
+    lower(&cst(source), source, interner)
+}
+
+/// Parses all the expressions in the source code, recovering from syntax errors
+///
+/// Instead of stopping at the first error, the error is recorded and the expression that couldn't
+/// be parsed is replaced with an `Expr_::Error` node. Returns the partial AST and all the errors,
+/// sorted by their position in the source code
+pub fn recover<'a>(source: Source<'a>, interner: &'a mut Interner) -> (Vec<Expr>, Vec<Error>) {
     let mut parser = Parser::new(source, interner);
-    let expr = try!(parser.expr());
+    let mut exprs = vec![];
 
     loop {
         match parser.lexer.peek() {
             None => break,
+            Some(&Ok(Spanned { node: Token_::Comment, .. })) |
             Some(&Ok(Spanned { node: Token_::Whitespace, .. })) => {
                 parser.next();
             },
             Some(_) => {
-                parser.next();
-                return Err((parser.spanned(Error_::ExpectedEndOfLine)))
-            }
+                parser.form_errors = parser.errors.len();
+                exprs.push(parser.expr_or_error())
+            },
         }
     }
 
-    Ok(expr)
+    let mut errors = parser.errors;
+
+    // NB unclosed delimiters are reported after the errors they enclose
+    errors.sort_by(|a, b| a.span.lo.cmp(&b.span.lo));
+
+    (exprs, errors)
+}
+
+/// Parses the source code into a concrete syntax tree
+///
+/// This never fails: tokens that couldn't be lexed become error nodes, and the delimiters don't
+/// need to be balanced. Use `lower` to convert the tree into expressions
+pub fn cst(source: Source) -> Vec<Node> {
+    /// Parses the next node, the lexer must not be exhausted
+    fn node(lexer: &mut Peekable<Lexer>) -> Node {
+        let token = match lexer.next().unwrap() {
+            Err(error) => return error.map(Node_::Error),
+            Ok(token) => token,
+        };
+
+        match token.node {
+            Token_::Deref | Token_::Quasiquote | Token_::Quote | Token_::SpliceUnquote |
+            Token_::Unquote => {
+                let mut nodes = vec![];
+
+                // NB the trivia that precedes the operand belongs to the reader macro
+                loop {
+                    match lexer.peek().cloned() {
+                        None | Some(Ok(Spanned { node: Token_::Close(_), .. })) => break,
+                        Some(Ok(Spanned { node: Token_::Comment, .. })) |
+                        Some(Ok(Spanned { node: Token_::Whitespace, .. })) => {
+                            nodes.push(node(lexer))
+                        },
+                        Some(_) => {
+                            nodes.push(node(lexer));
+                            break
+                        },
+                    }
+                }
+
+                let hi = nodes.last().map_or(token.span.hi, |node| node.span.hi);
+                let span = Span::new(token.span.file, token.span.lo, hi);
+
+                Spanned::new(span, Node_::ReaderMacro(token, nodes))
+            },
+            Token_::Open(delim) => {
+                let open = Spanned::new(token.span, delim);
+                let mut nodes = vec![];
+
+                let mut close = None;
+
+                // NB any close delimiter closes the sequence, even if it doesn't match
+                loop {
+                    match lexer.peek().cloned() {
+                        None => break,
+                        Some(Ok(Spanned { node: Token_::Close(delim), span })) => {
+                            lexer.next();
+                            close = Some(Spanned::new(span, delim));
+                            break
+                        },
+                        Some(_) => nodes.push(node(lexer)),
+                    }
+                }
+
+                let hi = match close {
+                    None => nodes.last().map_or(token.span.hi, |node| node.span.hi),
+                    Some(close) => close.span.hi,
+                };
+                let span = Span::new(token.span.file, token.span.lo, hi);
+
+                Spanned::new(span, Node_::Seq(open, nodes, close))
+            },
+            _ => token.map(Node_::Token),
+        }
+    }
+
+    let mut lexer = Lexer::new(source).peekable();
+    let mut nodes = vec![];
+
+    while lexer.peek().is_some() {
+        nodes.push(node(&mut lexer));
+    }
+
+    nodes
+}
+
+/// Converts the concrete syntax tree of the source code into expressions, skipping the trivia
+///
+/// Stops at the first syntax error. This is the strict parser, `expr` and `exprs` parse the source
+/// code this way
+pub fn lower<'a>(nodes: &[Node], source: Source<'a>, interner: &'a mut Interner)
+    -> Result<Vec<Expr>, Error>
+{
+    let mut parser = Parser::new(source, interner);
+
+    nodes.iter().filter(|node| !node.node.is_trivia()).map(|node| parser.node(node)).collect()
 }
@@ -1,47 +1,540 @@
 //! Pretty printing
+//!
+//! The code is first converted into a document, which describes the text and the places where
+//! it can be broken into several lines, and then the document gets laid out to fit in a line
+//! width, in the style of Wadler's "A prettier printer"
 
-use syntax::ast::{Expr, Expr_};
-use syntax::codemap::Source;
+use std::cmp;
+use std::usize;
 
-/// Pretty prints an expression
-pub fn expr(expr: &Expr, source: &Source) -> String {
-    let mut string = String::new();
-    expr_(&mut string, expr, source);
-    string
+use unicode_width::UnicodeWidthStr;
+
+use syntax::ast::{Expr, Expr_, Operator};
+use syntax::codemap::{Source, Spanned};
+use syntax::cst::{Node, Node_};
+use syntax::parse::lexer::{Delim, Token_};
+use util::interner::Interner;
+
+/// Pretty prints an expression in a single line
+pub fn expr(expr: &Expr, source: Source, interner: &Interner) -> String {
+    layout(&expr_(expr, source, interner, false), usize::MAX)
 }
 
-fn expr_(string: &mut String, expr: &Expr, source: &Source) {
-    fn seq(string: &mut String, exprs: &[Expr], source: &Source) {
-        let mut is_first = true;
+/// Formats a source file, given its concrete syntax tree, to fit in `width` columns
+///
+/// Whitespace is normalized: sequences are broken into several lines, and indented, only if they
+/// don't fit in one line, and top-level forms are separated by a newline, or by an empty line if
+/// there was at least one between them. Comments are kept, in their own line or at the end of
+/// the previous line
+pub fn nodes(nodes: &[Node], source: Source, width: usize) -> String {
+    let mut docs = vec![];
+    // NB newlines in the whitespace since the previous node
+    let mut newlines = 0;
 
-        for expr in exprs {
-            if is_first {
-                is_first = false;
-            } else {
-                string.push(' ');
-            }
+    for node in nodes {
+        match node.node {
+            Node_::Token(Token_::Whitespace) => {
+                newlines += source[node.span].chars().filter(|&c| c == '\n').count();
+
+                continue
+            },
+            Node_::Token(Token_::Comment) if newlines == 0 && !docs.is_empty() => {
+                docs.push(Doc::Text(String::from_str(" ")));
+                docs.push(comment(node, source));
+            },
+            _ => {
+                if !docs.is_empty() {
+                    docs.push(Doc::HardLine);
+
+                    if newlines > 1 {
+                        docs.push(Doc::HardLine);
+                    }
+                }
+
+                match node.node {
+                    Node_::Token(Token_::Comment) => docs.push(comment(node, source)),
+                    _ => docs.push(node_(node, source, false)),
+                }
+            },
+        }
+
+        newlines = 0;
+    }
+
+    if !docs.is_empty() {
+        docs.push(Doc::HardLine);
+    }
 
-            expr_(string, expr, source)
+    layout(&Doc::Concat(docs), width)
+}
+
+/// A document
+enum Doc {
+    /// Sets the indentation of the document to the current column
+    Align(Box<Doc>),
+    /// A comment, which ends the line, and may not fit in it
+    Comment(String),
+    /// Several documents, one after the other
+    Concat(Vec<Doc>),
+    /// The lines of the document are laid out as spaces if the whole group fits in the rest of
+    /// the line, otherwise all of them are laid out as newlines
+    Group(Box<Doc>),
+    /// A newline, even if the enclosing group fits, e.g. after a comment
+    HardLine,
+    /// The first document if the enclosing group is laid out as several lines, otherwise the
+    /// second one
+    IfBreak(Box<Doc>, Box<Doc>),
+    /// A space or a newline, depending on the enclosing group
+    Line,
+    /// Increases the indentation of the document
+    Nest(usize, Box<Doc>),
+    /// Text
+    Text(String),
+}
+
+/// An element of a sequence
+enum Elem {
+    /// A comment, and whether it's in the same line as the previous element
+    Comment(Doc, bool),
+    /// Any other element
+    Form(Doc),
+}
+
+impl Elem {
+    /// Returns the document of the element, if it's not a comment
+    fn form(&self) -> Option<&Doc> {
+        match *self {
+            Elem::Comment(..) => None,
+            Elem::Form(ref doc) => Some(doc),
         }
     }
+}
+
+/// The head of a list
+enum Head {
+    /// A special operator
+    Operator(Operator),
+    /// A symbol, i.e. a function or a macro
+    Symbol,
+    /// Anything else, e.g. a list of numbers
+    Other,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Break,
+    Flat,
+}
+
+/// How a sequence is laid out when it doesn't fit in one line
+#[derive(Clone, Copy)]
+enum Style {
+    /// `(f a` then `   b` in the next line, the arguments are aligned with the first one
+    Call,
+    /// `[a` then ` b` in the next line, the elements are aligned with the first one
+    Data,
+    /// `[a   1` then ` bcd 2` in the next line, like `Data` but with a key-value pair in each
+    /// line, e.g. maps and binding vectors. The values are aligned, unless a key is not an atom
+    Pairs,
+    /// `(let* [a 1]` then `  a` in the next line, the first `n` arguments stay in the first line,
+    /// and the rest, i.e. the body, are indented
+    Special(usize),
+}
+
+/// Converts a comment into a document
+fn comment(node: &Node, source: Source) -> Doc {
+    // NB e.g. the `\r` of a `\r\n` line ending
+    Doc::Comment(String::from_str(source[node.span].trim_right()))
+}
+
+/// Converts an expression into a document, `is_bindings` is true if the expression is the
+/// binding vector of a `let*`
+fn expr_(expr: &Expr, source: Source, interner: &Interner, is_bindings: bool) -> Doc {
+    fn elems(exprs: &[Expr], source: Source, interner: &Interner, is_let: bool) -> Vec<Elem> {
+        exprs.iter().enumerate().map(|(i, expr)| {
+            Elem::Form(expr_(expr, source, interner, is_let && i == 1))
+        }).collect()
+    }
 
     match expr.node {
-        Expr_::Bool(bool) => string.push_str(&bool.to_string()),
-        Expr_::Integer(integer) => string.push_str(&integer.to_string()),
-        Expr_::Keyword(_) => string.push_str(&source[expr.span]),
+        Expr_::BigInt(ref integer) => Doc::Text(integer.to_string()),
+        Expr_::Bool(bool) => Doc::Text(bool.to_string()),
+        Expr_::Error => Doc::Text(String::from_str(&source[expr.span])),
+        Expr_::Float(_) => Doc::Text(String::from_str(&source[expr.span])),
+        Expr_::Integer(integer) => Doc::Text(integer.to_string()),
+        Expr_::Keyword(_) => Doc::Text(String::from_str(&source[expr.span])),
         Expr_::List(ref exprs) => {
-            string.push('(');
-            seq(string, exprs, source);
-            string.push(')');
-        },
-        Expr_::Nil => string.push_str("nil"),
-        Expr_::Operator(_) => string.push_str(&source[expr.span]),
-        Expr_::String => string.push_str(&source[expr.span]),
-        Expr_::Symbol(_) => string.push_str(&source[expr.span]),
+            let head = match exprs.first().map(|expr| &expr.node) {
+                Some(&Expr_::Operator(operator)) => Head::Operator(operator),
+                Some(&Expr_::Symbol(_)) => Head::Symbol,
+                _ => Head::Other,
+            };
+            let is_let = match head {
+                Head::Operator(Operator::Let) => true,
+                _ => false,
+            };
+
+            seq("(", elems(exprs, source, interner, is_let), ")", list_style(head))
+        },
+        Expr_::Map(ref exprs) => {
+            seq("{", elems(exprs, source, interner, false), "}", Style::Pairs)
+        },
+        Expr_::Nil => Doc::Text(String::from_str("nil")),
+        Expr_::Operator(operator) => Doc::Text(String::from_str(operator.as_str())),
+        Expr_::String(_) => Doc::Text(String::from_str(&source[expr.span])),
+        // NB not printed from the source, because `@a` expands to `(deref a)`
+        Expr_::Symbol(ref name) => Doc::Text(String::from_str(&interner.get(name))),
+        // NB only the evaluator creates these
+        Expr_::Value(ref value) => Doc::Text(value.display(interner)),
         Expr_::Vector(ref exprs) => {
-            string.push('[');
-            seq(string, exprs, source);
-            string.push(']');
+            let style = if is_bindings { Style::Pairs } else { Style::Data };
+
+            seq("[", elems(exprs, source, interner, false), "]", style)
         },
     }
 }
+
+/// Checks if `doc`, laid out flat, and the `rest` of the documents, up to the end of the line,
+/// fit in `width` columns
+fn fits<'a>(mut width: usize, doc: &'a Doc, rest: &[(usize, Mode, &'a Doc)]) -> bool {
+    let mut stack = vec![(Mode::Flat, doc)];
+    let mut rest = rest.iter().rev();
+
+    loop {
+        let (mode, doc) = match stack.pop() {
+            None => match rest.next() {
+                None => return true,
+                Some(&(_, mode, doc)) => (mode, doc),
+            },
+            Some(next) => next,
+        };
+
+        match *doc {
+            Doc::Align(ref doc) | Doc::Group(ref doc) | Doc::Nest(_, ref doc) => {
+                stack.push((mode, &**doc))
+            },
+            // NB a comment can't be laid out flat, because it ends the line
+            Doc::Comment(_) => return mode == Mode::Break,
+            Doc::Concat(ref docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((mode, doc))
+                }
+            },
+            // NB a hard line can't be laid out flat
+            Doc::HardLine => return mode == Mode::Break,
+            Doc::IfBreak(ref broken, ref flat) => {
+                stack.push((mode, if mode == Mode::Break { &**broken } else { &**flat }))
+            },
+            Doc::Line => {
+                if mode == Mode::Break {
+                    return true
+                } else if width == 0 {
+                    return false
+                } else {
+                    width -= 1;
+                }
+            },
+            Doc::Text(ref text) => {
+                // NB e.g. a string that spans several lines
+                let line = text.split('\n').next().unwrap();
+                let line_width = UnicodeWidthStr::width(line);
+
+                if line_width > width {
+                    return false
+                } else if line.len() < text.len() {
+                    return mode == Mode::Break
+                } else {
+                    width -= line_width;
+                }
+            },
+        }
+    }
+}
+
+/// Lays out a document to fit in `width` columns
+fn layout(doc: &Doc, width: usize) -> String {
+    let mut string = String::new();
+    let mut col = 0;
+    // NB the documents that remain to be laid out, with their indentation and mode
+    let mut stack = vec![(0, Mode::Break, doc)];
+
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match *doc {
+            Doc::Align(ref doc) => stack.push((col, mode, &**doc)),
+            Doc::Comment(ref comment) => {
+                string.push_str(comment);
+                col += UnicodeWidthStr::width(&comment[..]);
+            },
+            Doc::Concat(ref docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((indent, mode, doc))
+                }
+            },
+            Doc::Group(ref doc) => {
+                let mode = if mode == Mode::Flat || fits(width.saturating_sub(col), doc, &stack) {
+                    Mode::Flat
+                } else {
+                    Mode::Break
+                };
+
+                stack.push((indent, mode, &**doc))
+            },
+            Doc::HardLine => col = newline(&mut string, indent),
+            Doc::IfBreak(ref broken, ref flat) => {
+                stack.push((indent, mode, if mode == Mode::Break { &**broken } else { &**flat }))
+            },
+            Doc::Line => {
+                if mode == Mode::Flat {
+                    string.push(' ');
+                    col += 1;
+                } else {
+                    col = newline(&mut string, indent);
+                }
+            },
+            Doc::Nest(n, ref doc) => stack.push((indent + n, mode, &**doc)),
+            Doc::Text(ref text) => {
+                string.push_str(text);
+
+                col = match text.rfind('\n') {
+                    None => col + UnicodeWidthStr::width(&text[..]),
+                    Some(i) => UnicodeWidthStr::width(&text[i + 1..]),
+                };
+            },
+        }
+    }
+
+    string
+}
+
+/// The style of a list that starts with `head`
+fn list_style(head: Head) -> Style {
+    match head {
+        Head::Operator(operator) => match operator {
+            Operator::Catch | Operator::Def | Operator::Defmacro | Operator::Fn | Operator::If |
+            Operator::Let => Style::Special(1),
+            Operator::Do | Operator::Try => Style::Special(0),
+            _ => Style::Call,
+        },
+        Head::Other => Style::Data,
+        Head::Symbol => Style::Call,
+    }
+}
+
+/// Starts a new line indented by `indent` spaces, returns the new column
+fn newline(string: &mut String, indent: usize) -> usize {
+    // NB don't leave trailing whitespace, e.g. in empty lines
+    let len = string.trim_right_matches(' ').len();
+    string.truncate(len);
+
+    string.push('\n');
+
+    for _ in 0..indent {
+        string.push(' ');
+    }
+
+    indent
+}
+
+/// Converts a node, that's not trivia, into a document, `is_bindings` is true if the node is the
+/// binding vector of a `let*`
+fn node_(node: &Node, source: Source, is_bindings: bool) -> Doc {
+    match node.node {
+        Node_::Error(_) | Node_::Token(_) => Doc::Text(String::from_str(&source[node.span])),
+        Node_::ReaderMacro(token, ref nodes) => {
+            let mut docs = vec![Doc::Text(String::from_str(&source[token.span]))];
+
+            for node in nodes {
+                match node.node {
+                    Node_::Token(Token_::Comment) => {
+                        docs.push(Doc::Text(String::from_str(" ")));
+                        docs.push(comment(node, source));
+                        docs.push(Doc::HardLine);
+                    },
+                    Node_::Token(Token_::Whitespace) => {},
+                    _ => docs.push(node_(node, source, false)),
+                }
+            }
+
+            Doc::Concat(docs)
+        },
+        Node_::Seq(open, ref nodes, close) => {
+            let head = match nodes.iter().filter(|node| !node.node.is_trivia()).next() {
+                Some(&Spanned { node: Node_::Token(Token_::Operator(operator)), .. }) => {
+                    Head::Operator(operator)
+                },
+                Some(&Spanned { node: Node_::Token(Token_::Symbol), .. }) => Head::Symbol,
+                _ => Head::Other,
+            };
+            let is_let = match head {
+                Head::Operator(Operator::Let) => true,
+                _ => false,
+            };
+
+            let mut elems = vec![];
+            let mut forms = 0;
+            // NB newlines in the whitespace since the previous element
+            let mut newlines = 0;
+
+            for node in nodes {
+                match node.node {
+                    Node_::Token(Token_::Whitespace) => {
+                        newlines += source[node.span].chars().filter(|&c| c == '\n').count();
+
+                        continue
+                    },
+                    Node_::Token(Token_::Comment) => {
+                        elems.push(Elem::Comment(comment(node, source), newlines == 0));
+                    },
+                    _ => {
+                        let is_let_bindings = open.node == Delim::Paren && is_let && forms == 1;
+
+                        elems.push(Elem::Form(node_(node, source, is_let_bindings)));
+                        forms += 1;
+                    },
+                }
+
+                newlines = 0;
+            }
+
+            let style = match open.node {
+                Delim::Brace => Style::Pairs,
+                Delim::Bracket => if is_bindings { Style::Pairs } else { Style::Data },
+                Delim::Paren => list_style(head),
+            };
+            // NB an unclosed sequence, which can't be lowered into an expression
+            let close = match close {
+                None => "",
+                Some(close) => &source[close.span],
+            };
+
+            seq(&source[open.span], elems, close, style)
+        },
+    }
+}
+
+/// Converts a sequence into a document
+fn seq(open: &str, elems: Vec<Elem>, close: &str, style: Style) -> Doc {
+    // NB the first argument of a call must be in the same line as the head
+    let style = match (style, elems.get(0), elems.get(1)) {
+        (Style::Call, Some(&Elem::Form(_)), Some(&Elem::Form(_))) => Style::Call,
+        (Style::Call, _, _) => Style::Data,
+        (style, _, _) => style,
+    };
+    // NB a sequence of atoms, e.g. `[1 2 3]`, is filled: it breaks only where the next element
+    // doesn't fit in the line
+    let is_fill = match style {
+        Style::Data => elems.iter().all(|elem| match *elem {
+            Elem::Form(Doc::Text(_)) => true,
+            _ => false,
+        }),
+        _ => false,
+    };
+    let ends_in_comment = match elems.last() {
+        Some(&Elem::Comment(..)) => true,
+        _ => false,
+    };
+    // NB the width of the widest key, if every key is an atom
+    let key_width = match style {
+        Style::Pairs => {
+            let mut key_width = Some(0);
+
+            for (i, doc) in elems.iter().filter_map(Elem::form).enumerate() {
+                key_width = match (key_width, doc) {
+                    (Some(max), &Doc::Text(ref key)) if i % 2 == 0 => {
+                        Some(cmp::max(max, UnicodeWidthStr::width(&key[..])))
+                    },
+                    (_, _) if i % 2 == 0 => None,
+                    (key_width, _) => key_width,
+                };
+            }
+
+            key_width
+        },
+        _ => None,
+    };
+
+    let mut docs = vec![];
+    let mut forms = 0;
+    let mut is_after_comment = false;
+    // NB the width of the last key
+    let mut key = 0;
+
+    for (i, elem) in elems.into_iter().enumerate() {
+        let separator = match elem {
+            _ if i == 0 => None,
+            _ if is_after_comment => Some(Doc::HardLine),
+            Elem::Comment(_, true) => Some(Doc::Text(String::from_str(" "))),
+            Elem::Comment(_, false) => Some(Doc::HardLine),
+            Elem::Form(_) => {
+                let is_same_line = match style {
+                    Style::Call => forms == 1,
+                    Style::Data => false,
+                    Style::Pairs => forms % 2 == 1,
+                    Style::Special(n) => forms <= n,
+                };
+
+                if let (true, Some(key_width)) = (is_same_line, key_width) {
+                    // NB in several lines, the values are aligned
+                    let padding = (key..key_width + 1).map(|_| ' ').collect();
+                    let space = Doc::Text(String::from_str(" "));
+
+                    Some(Doc::IfBreak(Box::new(Doc::Text(padding)), Box::new(space)))
+                } else if is_same_line {
+                    Some(Doc::Text(String::from_str(" ")))
+                } else if is_fill {
+                    Some(Doc::Group(Box::new(Doc::Line)))
+                } else {
+                    Some(Doc::Line)
+                }
+            },
+        };
+
+        if let Some(separator) = separator {
+            docs.push(separator);
+        }
+
+        match elem {
+            Elem::Comment(doc, _) => {
+                docs.push(doc);
+                is_after_comment = true;
+            },
+            Elem::Form(doc) => {
+                if let Doc::Text(ref text) = doc {
+                    key = UnicodeWidthStr::width(&text[..]);
+                }
+
+                docs.push(doc);
+                forms += 1;
+                is_after_comment = false;
+            },
+        }
+    }
+
+    // NB the close delimiter can't be part of the comment
+    if ends_in_comment {
+        docs.push(Doc::HardLine);
+    }
+
+    let open = Doc::Text(String::from_str(open));
+    let close = Doc::Text(String::from_str(close));
+
+    let doc = match style {
+        Style::Call => {
+            // NB the head and the space that follows it
+            let rest = Doc::Align(Box::new(Doc::Concat(docs.split_off(2))));
+
+            Doc::Concat(vec![open, Doc::Concat(docs), rest, close])
+        },
+        Style::Data | Style::Pairs => {
+            Doc::Concat(vec![open, Doc::Align(Box::new(Doc::Concat(docs))), close])
+        },
+        Style::Special(_) => {
+            let rest = docs.split_off(1);
+            let body = Doc::Nest(2, Box::new(Doc::Concat(rest)));
+
+            Doc::Align(Box::new(Doc::Concat(vec![open, Doc::Concat(docs), body, close])))
+        },
+    };
+
+    Doc::Group(Box::new(doc))
+}
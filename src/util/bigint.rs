@@ -0,0 +1,388 @@
+//! Arbitrary-precision integers
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::i64;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// An arbitrary-precision integer
+///
+/// Stored as a sign and a magnitude, the magnitude is a sequence of 32-bit digits with the least
+/// significant digit first and without leading (most significant) zeros. Zero is never negative,
+/// so equal integers have the same representation
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+const BASE: u64 = 1 << 32;
+
+impl BigInt {
+    /// Converts a machine integer into a big integer
+    pub fn from_i64(integer: i64) -> BigInt {
+        let negative = integer < 0;
+        // NB two's complement negation, so `i64::MIN` doesn't overflow
+        let magnitude = if negative {
+            (!(integer as u64)).wrapping_add(1)
+        } else {
+            integer as u64
+        };
+
+        BigInt::new(negative, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+
+    /// Parses an integer written in base `radix`, with an optional leading `-`. Returns `None` if
+    /// there are no digits or if a character is not a digit in that base
+    pub fn from_str_radix(string: &str, radix: u32) -> Option<BigInt> {
+        let (negative, digits) = if string.starts_with("-") {
+            (true, &string[1..])
+        } else {
+            (false, string)
+        };
+
+        if digits.is_empty() {
+            return None
+        }
+
+        let mut magnitude = vec![];
+
+        for c in digits.chars() {
+            match c.to_digit(radix) {
+                None => return None,
+                Some(digit) => mul_add_small(&mut magnitude, radix, digit),
+            }
+        }
+
+        Some(BigInt::new(negative, magnitude))
+    }
+
+    /// Is this integer negative?
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Is this integer odd?
+    pub fn is_odd(&self) -> bool {
+        self.digits.first().map_or(false, |&digit| digit & 1 == 1)
+    }
+
+    /// Is this integer zero?
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    /// Returns the absolute value of this integer
+    pub fn abs(&self) -> BigInt {
+        BigInt::new(false, self.digits.clone())
+    }
+
+    /// Returns the number of bits of the magnitude of this integer, zero has no bits
+    pub fn bits(&self) -> u64 {
+        match self.digits.last() {
+            None => 0,
+            Some(&digit) => self.digits.len() as u64 * 32 - digit.leading_zeros() as u64,
+        }
+    }
+
+    /// Raises this integer to the power of `exp`
+    pub fn pow(&self, mut exp: u64) -> BigInt {
+        let mut base = self.clone();
+        let mut power = BigInt::from_i64(1);
+
+        // NB exponentiation by squaring
+        while exp > 0 {
+            if exp & 1 == 1 {
+                power = &power * &base;
+            }
+
+            exp >>= 1;
+
+            if exp > 0 {
+                base = &base * &base;
+            }
+        }
+
+        power
+    }
+
+    /// Converts this integer into a float, rounding if it's not exactly representable
+    pub fn to_f64(&self) -> f64 {
+        let mut float = 0.;
+
+        for &digit in self.digits.iter().rev() {
+            float = float * BASE as f64 + digit as f64;
+        }
+
+        if self.negative { -float } else { float }
+    }
+
+    /// Converts this integer into a machine integer, returns `None` if it doesn't fit
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.digits.len() > 2 {
+            return None
+        }
+
+        let magnitude = self.digits.iter().rev().fold(0, |acc, &digit| acc << 32 | digit as u64);
+
+        if !self.negative && magnitude <= i64::MAX as u64 {
+            Some(magnitude as i64)
+        } else if self.negative && magnitude <= i64::MIN as u64 {
+            // NB two's complement negation, so `i64::MIN` doesn't overflow
+            Some((!magnitude).wrapping_add(1) as i64)
+        } else {
+            None
+        }
+    }
+
+    /// Creates an integer from its sign and magnitude, normalizing the representation
+    fn new(negative: bool, mut digits: Vec<u32>) -> BigInt {
+        trim(&mut digits);
+
+        BigInt {
+            negative: negative && !digits.is_empty(),
+            digits: digits,
+        }
+    }
+}
+
+impl<'a, 'b> Add<&'b BigInt> for &'a BigInt {
+    type Output = BigInt;
+
+    fn add(self, rhs: &BigInt) -> BigInt {
+        if self.negative == rhs.negative {
+            return BigInt::new(self.negative, add(&self.digits, &rhs.digits))
+        }
+
+        // NB opposite signs, the sign of the sum is the sign of the larger magnitude
+        match cmp(&self.digits, &rhs.digits) {
+            Ordering::Less => BigInt::new(rhs.negative, sub(&rhs.digits, &self.digits)),
+            _ => BigInt::new(self.negative, sub(&self.digits, &rhs.digits)),
+        }
+    }
+}
+
+/// Truncating division, i.e. the quotient is rounded towards zero as in `i64` division
+///
+/// # Panics
+///
+/// If `rhs` is zero
+impl<'a, 'b> Div<&'b BigInt> for &'a BigInt {
+    type Output = BigInt;
+
+    fn div(self, rhs: &BigInt) -> BigInt {
+        assert!(!rhs.is_zero(), "attempted to divide by zero");
+
+        BigInt::new(self.negative != rhs.negative, div(&self.digits, &rhs.digits))
+    }
+}
+
+impl<'a, 'b> Mul<&'b BigInt> for &'a BigInt {
+    type Output = BigInt;
+
+    fn mul(self, rhs: &BigInt) -> BigInt {
+        BigInt::new(self.negative != rhs.negative, mul(&self.digits, &rhs.digits))
+    }
+}
+
+impl<'a> Neg for &'a BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.digits.clone())
+    }
+}
+
+impl<'a, 'b> Sub<&'b BigInt> for &'a BigInt {
+    type Output = BigInt;
+
+    fn sub(self, rhs: &BigInt) -> BigInt {
+        self + &-rhs
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp(&self.digits, &other.digits),
+            (true, true) => cmp(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // NB the largest power of ten that fits in a digit
+        const CHUNK: u32 = 1_000_000_000;
+
+        if self.is_zero() {
+            return f.write_str("0")
+        }
+
+        let mut chunks = vec![];
+        let mut magnitude = self.digits.clone();
+
+        while !magnitude.is_empty() {
+            chunks.push(div_rem_small(&mut magnitude, CHUNK));
+        }
+
+        if self.negative {
+            try!(f.write_str("-"));
+        }
+
+        let mut chunks = chunks.iter().rev();
+
+        // NB only the most significant chunk is written without leading zeros
+        if let Some(chunk) = chunks.next() {
+            try!(write!(f, "{}", chunk));
+        }
+
+        for chunk in chunks {
+            try!(write!(f, "{:09}", chunk));
+        }
+
+        Ok(())
+    }
+}
+
+/// Adds two magnitudes
+fn add(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut sum = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0;
+
+    for (i, &digit) in long.iter().enumerate() {
+        let total = digit as u64 + short.get(i).map_or(0, |&digit| digit as u64) + carry;
+
+        sum.push(total as u32);
+        carry = total >> 32;
+    }
+
+    sum.push(carry as u32);
+    sum
+}
+
+/// Compares two magnitudes
+fn cmp(a: &[u32], b: &[u32]) -> Ordering {
+    match a.len().cmp(&b.len()) {
+        Ordering::Equal => a.iter().rev().cmp(b.iter().rev()),
+        ordering => ordering,
+    }
+}
+
+/// Divides two magnitudes, rounding the quotient down
+fn div(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if cmp(a, b) == Ordering::Less {
+        return vec![]
+    }
+
+    if b.len() == 1 {
+        let mut quotient = a.to_vec();
+        div_rem_small(&mut quotient, b[0]);
+        return quotient
+    }
+
+    // NB binary long division, one bit of the quotient per step
+    let mut quotient = vec![0; a.len()];
+    let mut remainder = vec![];
+
+    for i in (0..a.len() * 32).rev() {
+        let bit = a[i / 32] >> (i % 32) & 1;
+
+        mul_add_small(&mut remainder, 2, bit);
+
+        if cmp(&remainder, b) != Ordering::Less {
+            remainder = sub(&remainder, b);
+            quotient[i / 32] |= 1 << (i % 32);
+        }
+    }
+
+    quotient
+}
+
+/// Divides a magnitude in place by a single digit, and returns the remainder
+fn div_rem_small(a: &mut Vec<u32>, b: u32) -> u32 {
+    let mut remainder = 0;
+
+    for digit in a.iter_mut().rev() {
+        let dividend = remainder << 32 | *digit as u64;
+
+        *digit = (dividend / b as u64) as u32;
+        remainder = dividend % b as u64;
+    }
+
+    trim(a);
+    remainder as u32
+}
+
+/// Multiplies two magnitudes
+fn mul(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut product = vec![0; a.len() + b.len()];
+
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+
+        for (j, &y) in b.iter().enumerate() {
+            let total = product[i + j] as u64 + x as u64 * y as u64 + carry;
+
+            product[i + j] = total as u32;
+            carry = total >> 32;
+        }
+
+        product[i + b.len()] = carry as u32;
+    }
+
+    product
+}
+
+/// Computes `a * b + c` in place, where `b` and `c` are single digits
+fn mul_add_small(a: &mut Vec<u32>, b: u32, c: u32) {
+    let mut carry = c as u64;
+
+    for digit in a.iter_mut() {
+        let total = *digit as u64 * b as u64 + carry;
+
+        *digit = total as u32;
+        carry = total >> 32;
+    }
+
+    if carry != 0 {
+        a.push(carry as u32);
+    }
+}
+
+/// Subtracts two magnitudes, `a` must not be smaller than `b`
+fn sub(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+
+    for (i, &digit) in a.iter().enumerate() {
+        let subtrahend = b.get(i).map_or(0, |&digit| digit as u64) + borrow;
+
+        if digit as u64 >= subtrahend {
+            difference.push((digit as u64 - subtrahend) as u32);
+            borrow = 0;
+        } else {
+            difference.push((digit as u64 + BASE - subtrahend) as u32);
+            borrow = 1;
+        }
+    }
+
+    trim(&mut difference);
+    difference
+}
+
+/// Removes the leading zeros of a magnitude
+fn trim(digits: &mut Vec<u32>) {
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+}
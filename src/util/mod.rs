@@ -1,3 +1,4 @@
 //! Utilities
 
+pub mod bigint;
 pub mod interner;
@@ -1,26 +1,54 @@
 #![allow(dead_code)]
 
-use lisp::eval::env::Stack;
-use lisp::eval;
-use lisp::syntax::codemap::Source;
+use lisp::eval::env::Env;
+use lisp::eval::{Value, self};
+use lisp::syntax::codemap::{Source, SourceMap};
 use lisp::syntax::parse;
 use lisp::util::interner::Interner;
 
 pub use lisp::eval::Error_;
 
-pub fn eq(input: &str, output: &str, env: &mut Stack, interner: &mut Interner) {
+pub fn eq(input: &str, output: &str, env: &Env, interner: &mut Interner) {
     let source = Source::new(input);
+    let ref mut map = SourceMap::new();
 
     let ast = parse::expr(source, interner).unwrap();
 
-    assert_eq!(output, eval::expr(&ast, source, env).unwrap().display(interner))
+    assert_eq!(output, eval::expr(&ast, env, map, interner).unwrap().display(interner))
 }
 
-pub fn err(input: &str, error: eval::Error_, env: &mut Stack, interner: &mut Interner) {
+pub fn err(input: &str, error: eval::Error_, env: &Env, interner: &mut Interner) {
     let source = Source::new(input);
+    let ref mut map = SourceMap::new();
 
-    assert_eq!{
-        error,
-        eval::expr(&parse::expr(source, interner).unwrap(), source, env).unwrap_err().node
-    }
+    let ast = parse::expr(source, interner).unwrap();
+
+    assert_eq!(error, eval::expr(&ast, env, map, interner).unwrap_err().node)
+}
+
+pub fn span(input: &str, expected: &str, env: &Env, interner: &mut Interner) {
+    let source = Source::new(input);
+    let ref mut map = SourceMap::new();
+
+    let ast = parse::expr(source, interner).unwrap();
+
+    assert_eq!(expected, &source[eval::expr(&ast, env, map, interner).unwrap_err().span])
+}
+
+pub fn value(input: &str, env: &Env, interner: &mut Interner) -> Value {
+    let source = Source::new(input);
+    let ref mut map = SourceMap::new();
+
+    let ast = parse::expr(source, interner).unwrap();
+
+    eval::expr(&ast, env, map, interner).unwrap()
+}
+
+pub fn ok(input: &str, env: &Env, interner: &mut Interner) {
+    let source = Source::new(input);
+    let ref mut map = SourceMap::new();
+
+    let ast = parse::expr(source, interner).unwrap();
+
+    assert!(eval::expr(&ast, env, map, interner).is_ok())
 }
@@ -1,18 +1,36 @@
 extern crate lisp;
 
+use lisp::diagnostics;
+use lisp::repl::Input;
 use lisp::util::interner::Interner;
-use lisp::syntax::codemap::Source;
+use lisp::syntax::Error_;
+use lisp::syntax::ast::Expr_;
+use lisp::syntax::codemap::{File, Location, Source, SourceMap};
+use lisp::syntax::cst::{self, Node_};
+use lisp::syntax::parse::lexer::{Delim, Token_};
 use lisp::syntax::parse;
 use lisp::syntax::pp;
 
 fn eq(source: &str, expected_repr: &str, interner: &mut Interner) {
     let source = Source::new(source);
     let expr = parse::expr(source, interner).unwrap();
-    let repr = pp::expr(&expr, source);
+    let repr = pp::expr(&expr, source, interner);
 
     assert_eq!(repr, expected_repr)
 }
 
+fn recover(source: &str, interner: &mut Interner) -> (Vec<String>, Vec<(Error_, usize, usize)>)
+{
+    let source = Source::new(source);
+    let (exprs, errors) = parse::recover(source, interner);
+    let exprs = exprs.iter().map(|expr| pp::expr(expr, source, interner)).collect();
+    let errors = errors.iter().map(|error| {
+        (error.node, error.span.lo, error.span.hi)
+    }).collect();
+
+    (exprs, errors)
+}
+
 #[test]
 fn nil_true_false() {
     let ref mut interner = Interner::new();
@@ -28,6 +46,52 @@ fn numbers() {
 
     eq("1", "1", interner);
     eq("  7   ", "7", interner);
+    eq("-5", "-5", interner);
+    eq("1.5", "1.5", interner);
+    eq("-0.25", "-0.25", interner);
+    eq("1e-3", "1e-3", interner);
+
+    match parse::expr(Source::new("1e-3"), interner).unwrap().node {
+        Expr_::Float(float) => assert_eq!(float, 0.001),
+        _ => panic!("expected a float"),
+    }
+
+    let error = parse::expr(Source::new("1.2.3"), interner).unwrap_err();
+    assert!(match error.node { Error_::InvalidNumber => true, _ => false });
+
+    let error = parse::expr(Source::new("123abc"), interner).unwrap_err();
+    assert!(match error.node { Error_::InvalidNumber => true, _ => false });
+}
+
+#[test]
+fn integers() {
+    let ref mut interner = Interner::new();
+
+    eq("+42", "42", interner);
+    eq("-42", "-42", interner);
+    eq("-", "-", interner);
+    eq("-foo", "-foo", interner);
+    eq("(- 5)", "(- 5)", interner);
+    eq("0xff", "255", interner);
+    eq("-0x10", "-16", interner);
+    eq("0o17", "15", interner);
+    eq("0b1010", "10", interner);
+    eq("1_000_000", "1000000", interner);
+    eq("0x_ff_ff", "65535", interner);
+    eq("0x1_0000_0000_0000_0000", "18446744073709551616", interner);
+    eq("-0x8000_0000_0000_0000", "-9223372036854775808", interner);
+
+    let error = parse::expr(Source::new("0b102"), interner).unwrap_err();
+    assert!(match error.node { Error_::InvalidDigit => true, _ => false });
+    assert_eq!((error.span.lo, error.span.hi), (4, 5));
+
+    let error = parse::expr(Source::new("(+ 1 -0xfg)"), interner).unwrap_err();
+    assert!(match error.node { Error_::InvalidDigit => true, _ => false });
+    assert_eq!((error.span.lo, error.span.hi), (9, 10));
+
+    let error = parse::expr(Source::new("0x"), interner).unwrap_err();
+    assert!(match error.node { Error_::InvalidNumber => true, _ => false });
+    assert_eq!((error.span.lo, error.span.hi), (0, 2));
 }
 
 #[test]
@@ -48,9 +112,31 @@ fn string() {
     eq("\"abc\"", "\"abc\"", interner);
     eq("   \"abc\"   ", "\"abc\"", interner);
     eq("\"abc (with parens)\"", "\"abc (with parens)\"", interner);
-    // TODO handle escaping
-    //eq(r#""abc\"def""#, "", interner);
+    eq(r#""abc\"def""#, r#""abc\"def""#, interner);
     eq(r#""abc\ndef""#, "\"abc\\ndef\"", interner);
+    eq(r#""""#, r#""""#, interner);
+}
+
+#[test]
+fn escapes() {
+    let ref mut interner = Interner::new();
+
+    let source = Source::new(r#""a\n\t\\\"\u{48}\u{1F600}""#);
+
+    match parse::expr(source, interner).unwrap().node {
+        Expr_::String(ref string) => assert_eq!(string, "a\n\t\\\"H\u{1F600}"),
+        _ => panic!("expected a string"),
+    }
+
+    let error = parse::expr(Source::new(r#""ab\qc""#), interner).unwrap_err();
+    assert!(match error.node { Error_::UnknownCharacterEscape => true, _ => false });
+    assert_eq!((error.span.lo, error.span.hi), (3, 5));
+
+    let error = parse::expr(Source::new(r#""\u{110000}""#), interner).unwrap_err();
+    assert!(match error.node { Error_::InvalidUnicodeEscape => true, _ => false });
+    assert_eq!((error.span.lo, error.span.hi), (1, 11));
+
+    assert!(parse::expr(Source::new(r#""abc\""#), interner).is_err());
 }
 
 #[test]
@@ -65,6 +151,21 @@ fn lists() {
     eq("(** 1 2)", "(** 1 2)", interner);
 }
 
+#[test]
+fn maps() {
+    let ref mut interner = Interner::new();
+
+    eq("{}", "{}", interner);
+    eq(r#"{"abc" 1}"#, r#"{"abc" 1}"#, interner);
+    eq(r#"{"a" {"b" 2}}"#, r#"{"a" {"b" 2}}"#, interner);
+    eq("{ :a  {:b   {  :c 3  }  }  }", "{:a {:b {:c 3}}}", interner);
+
+    assert!(parse::expr(Source::new("{:a 1 :b}"), interner).is_err());
+    assert!(parse::expr(Source::new("{:a 1"), interner).is_err());
+    assert!(parse::expr(Source::new("(+ 1 2))"), interner).is_err());
+    assert!(parse::expr(Source::new(")"), interner).is_err());
+}
+
 #[test]
 fn commas() {
     let ref mut interner = Interner::new();
@@ -72,9 +173,77 @@ fn commas() {
     eq("(1 2, 3,,,,),,", "(1 2 3)", interner);
 }
 
-// TODO implement quoting
 #[test]
-#[ignore]
+fn comments() {
+    let ref mut interner = Interner::new();
+
+    eq("1 ; comment after expression", "1", interner);
+    eq("1; comment after expression", "1", interner);
+    eq("(1 ; comment inside a list\n 2)", "(1 2)", interner);
+    eq(";; leading comment\n[1 2]", "[1 2]", interner);
+}
+
+#[test]
+fn exprs() {
+    let ref mut interner = Interner::new();
+
+    let source = Source::new("; a file\n(def! a 1)\r\n\n(+ a 2) ; trailing\n:b");
+    let exprs = parse::exprs(source, interner).unwrap();
+    let reprs = exprs.iter().map(|expr| pp::expr(expr, source, interner)).collect::<Vec<_>>();
+
+    assert_eq!(reprs, ["(def! a 1)", "(+ a 2)", ":b"]);
+
+    assert!(parse::exprs(Source::new("; nothing but a comment"), interner).unwrap().is_empty());
+    assert!(parse::exprs(Source::new("(+ 1 2)\n(+ 3"), interner).is_err());
+}
+
+#[test]
+fn multiline() {
+    let ref mut interner = Interner::new();
+
+    // NB what the REPL has read after each line of a pasted definition
+    let error = parse::exprs(Source::new("(def! inc\n"), interner).unwrap_err();
+    assert!(match error.node { Error_::UnclosedDelimiter => true, _ => false });
+
+    let error = parse::exprs(Source::new("(def! inc\n  (fn* [x]\n"), interner).unwrap_err();
+    assert!(match error.node { Error_::UnclosedDelimiter => true, _ => false });
+
+    let source = Source::new("(def! inc\n  (fn* [x]\n    (+ x 1)))\n");
+    let exprs = parse::exprs(source, interner).unwrap();
+    assert_eq!(pp::expr(&exprs[0], source, interner), "(def! inc (fn* [x] (+ x 1)))");
+
+    let error = parse::exprs(Source::new("(def! inc\n  (fn* [x]))]\n"), interner).unwrap_err();
+    assert!(match error.node { Error_::UnexpectedCloseDelimiter => true, _ => false });
+}
+
+#[test]
+fn repl_input() {
+    let mut input = Input::new();
+
+    assert_eq!(input.push_line("(+ 1 2)"), Some(String::from("(+ 1 2)\n")));
+
+    assert_eq!(input.push_line("(def! inc"), None);
+    assert_eq!(input.push_line("  (fn* [x]"), None);
+    let text = input.push_line("    (+ x 1)))");
+    assert_eq!(text, Some(String::from("(def! inc\n  (fn* [x]\n    (+ x 1)))\n")));
+
+    // NB delimiters and comments inside a string don't count
+    assert_eq!(input.push_line(r#"(str "a ( ;"#), None);
+    assert_eq!(input.push_line(r#"b" 1"#), None);
+    assert_eq!(input.push_line(")"), Some(String::from("(str \"a ( ;\nb\" 1\n)\n")));
+
+    assert_eq!(input.push_line(r#""abc"#), None);
+    assert_eq!(input.push_line(r#"def""#), Some(String::from("\"abc\ndef\"\n")));
+
+    assert_eq!(input.push_line("(a ; )"), None);
+    assert_eq!(input.push_line(")"), Some(String::from("(a ; )\n)\n")));
+
+    // NB errors complete the input, so they get reported right away
+    assert_eq!(input.push_line(")"), Some(String::from(")\n")));
+    assert_eq!(input.push_line("([)"), Some(String::from("([)\n")));
+}
+
+#[test]
 fn quoting() {
     let ref mut interner = Interner::new();
 
@@ -85,6 +254,8 @@ fn quoting() {
     eq(("~1"), "(unquote 1)", interner);
     eq(("~(1 2 3)"), "(unquote (1 2 3))", interner);
     eq(("~@(1 2 3)"), "(splice-unquote (1 2 3))", interner);
+    eq(("@a"), "(deref a)", interner);
+    eq(("(swap! a inc)"), "(swap! a inc)", interner);
 }
 
 #[test]
@@ -96,4 +267,284 @@ fn errors() {
     assert!(parse::expr(Source::new("\"abc"), interner).is_err());
 }
 
+#[test]
+fn recovery() {
+    let ref mut interner = Interner::new();
+
+    let text = "(def! a (+ 1 2]\n(def! b \"\\q\")\n(def! c {:a})\n)";
+    let (exprs, errors) = recover(text, interner);
+    assert_eq!(exprs, ["(def! a (+ 1 2))", "(def! b \"\\q\")", "(def! c {:a })", ")"]);
+    assert_eq!(errors, [
+        (Error_::UnclosedDelimiter, 0, 1),
+        (Error_::IncorrectCloseDelimiter, 14, 15),
+        (Error_::UnknownCharacterEscape, 25, 27),
+        (Error_::ExpectedMapValue, 39, 41),
+        (Error_::UnexpectedCloseDelimiter, 44, 45),
+    ]);
+
+    // NB the close delimiter belongs to the enclosing vector
+    let (exprs, errors) = recover("[1 (2 3]", interner);
+    assert_eq!(exprs, ["[1 (2 3)]"]);
+    assert_eq!(errors, [(Error_::IncorrectCloseDelimiter, 7, 8)]);
+
+    let (exprs, errors) = recover("(+ 1 [2 3", interner);
+    assert_eq!(exprs, ["(+ 1 [2 3])"]);
+    assert_eq!(errors, [(Error_::UnclosedDelimiter, 0, 1), (Error_::UnclosedDelimiter, 5, 6)]);
+
+    let (exprs, errors) = recover("(+ 0b12 def! 1) (foo ')", interner);
+    assert_eq!(exprs, ["(+ 0b12 def! 1)", "(foo (quote ))"]);
+    assert_eq!(errors, [
+        (Error_::InvalidDigit, 6, 7),
+        (Error_::OperatorNotAllowedHere, 8, 12),
+        (Error_::UnexpectedCloseDelimiter, 22, 23),
+    ]);
+
+    let (exprs, errors) = recover("(+ 1 2) ; fine", interner);
+    assert_eq!(exprs, ["(+ 1 2)"]);
+    assert!(errors.is_empty());
+
+    // NB valid code that's not indented isn't split into several forms
+    let (exprs, errors) = recover("(def! f\n(fn* [] 1))\n(let* [a\n[1 2]]\n(+ a 1))", interner);
+    assert_eq!(exprs, ["(def! f (fn* [] 1))", "(let* [a [1 2]] (+ a 1))"]);
+    assert!(errors.is_empty());
+
+    let (exprs, errors) = recover("(def! f\n(fn* [] 1)\n(def! g 2)", interner);
+    assert_eq!(exprs, ["(def! f)", "(fn* [] 1)", "(def! g 2)"]);
+    assert_eq!(errors, [(Error_::UnclosedDelimiter, 0, 1)]);
+}
+
+#[test]
+fn lossless() {
+    let ref mut interner = Interner::new();
+
+    let sources = [
+        "",
+        "(def! a ; the answer\r\n  42)",
+        "{:a 1, :b [2 3]}  ",
+        "' a @b ~@ ; splice\nc `(d ~e)",
+        "(+ 1 2] ) (unclosed [\"unterminated",
+        "'",
+        "(a ') \"\\q\" 0b12 \\ λ",
+    ];
+
+    for &text in sources.iter() {
+        let source = Source::new(text);
+        let nodes = parse::cst(source);
+
+        assert_eq!(cst::text(&nodes, source), text);
+    }
+
+    let source = Source::new("(a ; b\n 1]");
+    let nodes = parse::cst(source);
+    assert_eq!(nodes.len(), 1);
+    if let Node_::Seq(open, ref nodes, close) = nodes[0].node {
+        assert_eq!(open.node, Delim::Paren);
+        assert_eq!(close.map(|close| close.node), Some(Delim::Bracket));
+
+        let tokens = nodes.iter().map(|node| match node.node {
+            Node_::Token(token) => token,
+            _ => unreachable!(),
+        }).collect::<Vec<_>>();
+
+        assert_eq!(tokens, [
+            Token_::Symbol,
+            Token_::Whitespace,
+            Token_::Comment,
+            Token_::Whitespace,
+            Token_::Number,
+        ]);
+    } else {
+        unreachable!()
+    }
+
+    // NB the recovering parser produces the same expressions as the strict parser, and reports
+    // an error if the strict parser does
+    let sources = [
+        "(def! a ; the answer\n  42)\n{:a 1, :b [2 3]}",
+        "(let* [x 'if y @z] `(~x ~@ ; splice\n y))",
+        "[1 2 3] \"a\\nb\" 0xff_ff 1.5e3 nil true :k",
+        "(+ 1 2]",
+        "(+ 1 [2",
+        "(a ')",
+        "(a ' ; comment\n)",
+        "'",
+        "[def!]",
+        "' def!",
+        "{:a 1 :b}",
+        "(+ 1 2))",
+        "(\"\\q\" 0b12)",
+        "(\\ 1)",
+    ];
+
+    for &text in sources.iter() {
+        let source = Source::new(text);
+
+        match (parse::exprs(source, interner), parse::recover(source, interner)) {
+            (Ok(expected), (exprs, ref errors)) if errors.is_empty() => {
+                let expected = expected.iter().map(|expr| pp::expr(expr, source, interner));
+                let exprs = exprs.iter().map(|expr| pp::expr(expr, source, interner));
+
+                assert_eq!(exprs.collect::<Vec<_>>(), expected.collect::<Vec<_>>());
+            },
+            (Err(_), (_, ref errors)) if !errors.is_empty() => {},
+            (expected, (_, errors)) => panic!("{}: {:?} != {:?}", text, errors, expected),
+        }
+    }
+}
+
+#[test]
+fn format() {
+    fn fmt(text: &str, width: usize) -> String {
+        let source = Source::new(text);
+
+        pp::nodes(&parse::cst(source), source, width)
+    }
+
+    let text = "\
+; fib
+(def! fib (fn* [n] (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))) ; slow
+
+
+(def! a   1)";
+    let expected = "\
+; fib
+(def! fib
+  (fn* [n]
+    (if (< n 2)
+      n
+      (+ (fib (- n 1))
+         (fib (- n 2)))))) ; slow
+
+(def! a 1)
+";
+    assert_eq!(fmt(text, 40), expected);
+    assert_eq!(fmt(expected, 40), expected);
+
+    // NB binding vectors have a binding in each line, with the values aligned
+    let text = "(let* [total 0 first (first xs) rest-of-them (rest xs)] (+ total first))\n";
+    let expected = "\
+(let* [total        0
+       first        (first xs)
+       rest-of-them (rest xs)]
+  (+ total first))
+";
+    assert_eq!(fmt(text, 40), expected);
+    assert_eq!(fmt(expected, 40), expected);
+    assert_eq!(fmt(text, 80), text);
+
+    // NB values are only aligned if every key is an atom
+    let text = "{:a 1 :bcd 2 (f x) 3}";
+    assert_eq!(fmt(text, 10), "{:a 1\n :bcd 2\n (f x) 3}\n");
+    let text = "{:a 1 :bcd 2}";
+    assert_eq!(fmt(text, 10), "{:a   1\n :bcd 2}\n");
+
+    // NB sequences of atoms are filled, and comments don't need to fit in the line
+    let text = "(foo-bar [1 2 3 4 5 6 7 8 9 10 11 12] ; numbers\n  {:a 1, :b 2})";
+    let expected = "\
+(foo-bar [1 2 3 4 5
+          6 7 8 9 10
+          11 12] ; numbers
+         {:a 1
+          :b 2})
+";
+    assert_eq!(fmt(text, 20), expected);
+
+    let text = "(do ; first\n  (a)\n  ; then\n  (b) ; last\n)";
+    let expected = "\
+(do ; first
+  (a)
+  ; then
+  (b) ; last
+  )
+";
+    assert_eq!(fmt(text, 80), expected);
+
+    // NB CRLF line endings become LF line endings, also in comments
+    let text = "; a\r\n(def! a 1) ; b\r\n; c\r\n";
+    assert_eq!(fmt(text, 80), "; a\n(def! a 1) ; b\n; c\n");
+}
+
 // TODO optional tests
+
+#[test]
+fn codemap() {
+    let file = File::new(String::from("a.mal"), String::from("(def! a 1)\r\n\n  (+ a\n  λ)"));
+
+    assert_eq!(file.name(), "a.mal");
+    assert_eq!(file.location(0), Location { line: 1, col: 1 });
+    assert_eq!(file.location(6), Location { line: 1, col: 7 });
+    assert_eq!(file.location(12), Location { line: 2, col: 1 });
+    assert_eq!(file.location(15), Location { line: 3, col: 3 });
+    assert_eq!(file.location(24), Location { line: 4, col: 4 });
+    assert_eq!(file.line(1), "(def! a 1)");
+    assert_eq!(file.line(2), "");
+    assert_eq!(file.line(4), "  λ)");
+
+    let ref mut interner = Interner::new();
+    let mut map = SourceMap::new();
+    let a = map.add(String::from("a.mal"), String::from("(+ 1 2)"));
+    let b = map.add(String::from("b.mal"), String::from("\n(* 3 4)"));
+
+    assert!(a != b);
+    assert_eq!(map.get(b).unwrap().name(), "b.mal");
+    assert!(map.get(Source::new("").file()).is_none());
+
+    let expr = parse::expr(map.source(b), interner).unwrap();
+    assert_eq!(expr.span.file, b);
+    assert_eq!(map.get(expr.span.file).unwrap().location(expr.span.lo).line, 2);
+}
+
+#[test]
+fn diagnostics() {
+    let ref mut interner = Interner::new();
+    let mut map = SourceMap::new();
+
+    let text = "(def! a 1)\n\n(def! b\n  (+ a 2]\n";
+    let file = map.add(String::from("b.mal"), String::from(text));
+    let error = parse::exprs(map.source(file), interner).unwrap_err();
+
+    let expected = "\
+error: incorrect close delimiter
+ --> b.mal:4:9
+  |
+4 |   (+ a 2]
+  |         ^
+";
+    assert_eq!(diagnostics::syntax(error, &map), expected);
+
+    let text = (0..9).map(|_| "\n").collect::<String>() + "   :";
+    let file = map.add(String::from("c.mal"), text);
+    let error = parse::exprs(map.source(file), interner).unwrap_err();
+
+    let expected = "\
+error: empty keyword
+  --> c.mal:10:4
+   |
+10 |    :
+   |    ^
+";
+    assert_eq!(diagnostics::syntax(error, &map), expected);
+
+    let file = map.add(String::from("d.mal"), String::new());
+    let error = parse::expr(map.source(file), interner).unwrap_err();
+
+    let expected = "error: unexpected end of input\n --> d.mal:1:1\n  |\n1 | \n  | ^\n";
+    assert_eq!(diagnostics::syntax(error, &map), expected);
+
+    // NB the tabs that indent the line also indent the underline
+    let file = map.add(String::from("e.mal"), String::from("(def! b\n\t(+ a 2]\n"));
+    let error = parse::exprs(map.source(file), interner).unwrap_err();
+
+    let expected = "\
+error: incorrect close delimiter
+ --> e.mal:2:8
+  |
+2 | \t(+ a 2]
+  | \t      ^
+";
+    assert_eq!(diagnostics::syntax(error, &map), expected);
+
+    // NB without a file the location is unknown
+    let error = parse::exprs(Source::new("(+ 1"), interner).unwrap_err();
+    assert_eq!(diagnostics::syntax(error, &map), "error: un-closed delimiter\n");
+}
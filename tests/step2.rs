@@ -2,25 +2,194 @@ extern crate lisp;
 
 mod eval;
 
-use lisp::eval::env;
+use lisp::eval::{Arity, Type, env};
+use lisp::syntax::codemap::{Source, SourceMap};
+use lisp::syntax::parse;
 use lisp::util::interner::Interner;
 
 #[test]
 fn eq() {
     let ref mut interner = Interner::new();
-    let ref mut env = env::default(interner);
+    let ref env = env::default(interner);
 
     eval::eq("(+ 1 2)", "3", env, interner);
     eval::eq("(+ 5 (* 2 3))", "11", env, interner);
     eval::eq("(- (+ 5 (* 2 3)) 3)", "8", env, interner);
     eval::eq("(/ (- (+ 5 (* 2 3)) 3) 4)", "2", env, interner);
     eval::eq("(/ (- (+ 515 (* 222 311)) 302) 27)", "2565", env, interner);
+    eval::eq("(+ 0x10 -0b1 1_000)", "1015", env, interner);
 }
 
 #[test]
 fn err() {
     let ref mut interner = Interner::new();
-    let ref mut env = env::default(interner);
+    let ref env = env::default(interner);
 
     eval::err("(abc 1 2 3)", eval::Error_::UndefinedSymbol, env, interner);
+
+    let error = eval::Error_::ArityMismatch(Arity::AtLeast(1), 0);
+    eval::err("(-)", error, env, interner);
+    eval::span("(-)", "(-)", env, interner);
+
+    let error = eval::Error_::TypeMismatch(Type::Number, Type::Keyword);
+    eval::err("(* 2 :a)", error, env, interner);
+    eval::span("(* 2 :a)", ":a", env, interner);
+    eval::span("(- (+ 1 2) (+ 3 nil))", "nil", env, interner);
+
+    // NB the output of `parse::recover` contains error nodes
+    let source = Source::new("(+ 1 :) '(1 :)");
+    let ref mut map = SourceMap::new();
+    let (exprs, _) = parse::recover(source, interner);
+    for expr in &exprs {
+        let error = lisp::eval::expr(expr, env, map, interner).unwrap_err();
+        assert_eq!(error.node, eval::Error_::InvalidSyntax);
+        assert_eq!(&source[error.span], ":");
+    }
+}
+
+#[test]
+fn variadic() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("(+)", "0", env, interner);
+    eval::eq("(+ 1)", "1", env, interner);
+    eval::eq("(+ 1 2 3)", "6", env, interner);
+    eval::eq("(*)", "1", env, interner);
+    eval::eq("(* 2 3 4)", "24", env, interner);
+    eval::eq("(- 5)", "-5", env, interner);
+    eval::eq("(- 10 2 3)", "5", env, interner);
+    eval::eq("(/ 2)", "0", env, interner);
+    eval::eq("(/ 100 5 2)", "10", env, interner);
+
+    eval::eq("(< 1)", "true", env, interner);
+    eval::eq("(< 1 2 3)", "true", env, interner);
+    eval::eq("(< 1 3 2)", "false", env, interner);
+    eval::eq("(<= 1 1 2)", "true", env, interner);
+    eval::eq("(> 3 2 1)", "true", env, interner);
+    eval::eq("(> 3 3 1)", "false", env, interner);
+    eval::eq("(>= 3 3 1)", "true", env, interner);
+
+    eval::eq("(= 1)", "true", env, interner);
+    eval::eq("(= 1 1 1)", "true", env, interner);
+    eval::eq("(= 1 1 2)", "false", env, interner);
+    eval::eq("(= :a :a)", "true", env, interner);
+    eval::eq(r#"(= "a" "a" "b")"#, "false", env, interner);
+    eval::eq("(= [1 {:a (list 2)}] [1 {:a (list 2)}])", "true", env, interner);
+    eval::eq("(= 1 :a)", "false", env, interner);
+    eval::eq("(= nil nil)", "true", env, interner);
+
+    eval::span("(/ 1 2 0)", "0", env, interner);
+    eval::span("(< 1 2 :c)", ":c", env, interner);
+
+    let error = eval::Error_::ArityMismatch(Arity::AtLeast(1), 0);
+    eval::err("(<)", error, env, interner);
+}
+
+#[test]
+fn checked() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::ok("(def! max 9223372036854775807)", env, interner);
+    eval::ok("(def! min (- (- 0 max) 1))", env, interner);
+
+    eval::eq("(+ max 0)", "9223372036854775807", env, interner);
+    eval::eq("(- min 0)", "-9223372036854775808", env, interner);
+    eval::eq("(/ min 1)", "-9223372036854775808", env, interner);
+    eval::eq("(/ 7 (- 0 2))", "-3", env, interner);
+
+    eval::err("(/ 1 0)", eval::Error_::DivisionByZero, env, interner);
+    eval::span("(/ 1 (- 2 2))", "(- 2 2)", env, interner);
+    eval::err("(/ 9223372036854775808 0)", eval::Error_::DivisionByZero, env, interner);
+    eval::eq("(try* (/ 1 0) (catch* e e))", r#""attempted to divide by zero""#, env, interner);
+}
+
+#[test]
+fn bigint() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::ok("(def! max 9223372036854775807)", env, interner);
+    eval::ok("(def! min (- (- 0 max) 1))", env, interner);
+
+    eval::eq("123456789012345678901234567890", "123456789012345678901234567890", env, interner);
+    eval::eq("-9223372036854775809", "-9223372036854775809", env, interner);
+    eval::eq("(+ max 1)", "9223372036854775808", env, interner);
+    eval::eq("(- min 1)", "-9223372036854775809", env, interner);
+    eval::eq("(- min)", "9223372036854775808", env, interner);
+    eval::eq("(/ min (- 0 1))", "9223372036854775808", env, interner);
+    eval::eq("(* max 2)", "18446744073709551614", env, interner);
+    eval::eq("(abs min)", "9223372036854775808", env, interner);
+    eval::eq("(pow 2 64)", "18446744073709551616", env, interner);
+    eval::eq("(pow -1 100001)", "-1", env, interner);
+    eval::eq("(pow 1 100000000000000000000)", "1", env, interner);
+    eval::eq("(pow 0 100000)", "0", env, interner);
+    eval::eq("(pow 0 0)", "1", env, interner);
+    eval::err("(pow 2 100000)", eval::Error_::Domain, env, interner);
+    eval::span("(pow 2 100000)", "100000", env, interner);
+    eval::err("(pow (pow 10 1000) 1000)", eval::Error_::Domain, env, interner);
+    eval::err("(pow 2 100000000000000000000)", eval::Error_::Domain, env, interner);
+    let square = "9999999999999999999800000000000000000001";
+    eval::eq("(* 99999999999999999999 99999999999999999999)", square, env, interner);
+    eval::eq("(/ 100000000000000000000 7)", "14285714285714285714", env, interner);
+    eval::eq("(+ 0.5 18446744073709551616)", "18446744073709552000.0", env, interner);
+
+    // NB results that fit in 64 bits are small integers again
+    eval::eq("(+ max 1 (- 0 1))", "9223372036854775807", env, interner);
+    eval::eq("(- (* max 2) max)", "9223372036854775807", env, interner);
+    eval::eq("(= max (- (+ max 1) 1))", "true", env, interner);
+    eval::eq("(= (+ max 1) 9223372036854775808)", "true", env, interner);
+    eval::eq("(get {9223372036854775808 :a} (+ max 1))", ":a", env, interner);
+
+    eval::eq("(< min (- min 1))", "false", env, interner);
+    eval::eq("(< (- min 1) min 0 max (+ max 1))", "true", env, interner);
+    eval::eq("(> 1e30 (pow 10 29))", "true", env, interner);
+
+    eval::ok("(def! fact (fn* [n] (if (<= n 1) 1 (* n (fact (- n 1))))))", env, interner);
+    eval::eq("(fact 25)", "15511210043330985984000000", env, interner);
+    eval::eq("(/ (fact 25) (fact 23))", "600", env, interner);
+
+    let error = eval::Error_::IndexOutOfBounds;
+    eval::err("(nth [1 2] 9223372036854775808)", error, env, interner);
+}
+
+#[test]
+fn floats() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("1.5", "1.5", env, interner);
+    eval::eq("1e-3", "0.001", env, interner);
+    eval::eq("-0.25", "-0.25", env, interner);
+    eval::eq("1.0", "1.0", env, interner);
+    eval::eq("(+ 1 1.5)", "2.5", env, interner);
+    eval::eq("(* 2 0.5)", "1.0", env, interner);
+    eval::eq("(- 1.5)", "-1.5", env, interner);
+    eval::eq("(/ 1 2.0)", "0.5", env, interner);
+    eval::eq("(/ 1.0 0)", "inf", env, interner);
+    eval::eq("(/ 7 2)", "3", env, interner);
+
+    eval::eq("(< 1 1.5 2)", "true", env, interner);
+    eval::eq("(>= 2.0 2 1.5)", "true", env, interner);
+    eval::eq("(< 1 (sqrt -1))", "false", env, interner);
+    eval::eq("(= (sqrt -1) (sqrt -2))", "true", env, interner);
+    eval::eq("(get {(sqrt -1) :nan} (sqrt -2))", ":nan", env, interner);
+    eval::eq("(= 1 1.0)", "false", env, interner);
+    eval::eq("(= 0.5 (/ 1 2.0))", "true", env, interner);
+
+    eval::eq("(abs -2)", "2", env, interner);
+    eval::eq("(abs -2.5)", "2.5", env, interner);
+    eval::eq("(ceil 1.2)", "2.0", env, interner);
+    eval::eq("(floor -1.2)", "-2.0", env, interner);
+    eval::eq("(round 2.5)", "3.0", env, interner);
+    eval::eq("(round 7)", "7", env, interner);
+    eval::eq("(sqrt 16)", "4.0", env, interner);
+    eval::eq("(cos 0)", "1.0", env, interner);
+    eval::eq("(pow 2 10)", "1024", env, interner);
+    eval::eq("(pow 2 -1)", "0.5", env, interner);
+    eval::eq("(pow 4 0.5)", "2.0", env, interner);
+
+    let error = eval::Error_::TypeMismatch(Type::Number, Type::String);
+    eval::err(r#"(sin "0")"#, error, env, interner);
 }
@@ -2,13 +2,14 @@ extern crate lisp;
 
 mod eval;
 
+use lisp::eval::Value;
 use lisp::eval::env;
 use lisp::util::interner::Interner;
 
 #[test]
 fn env() {
     let ref mut interner = Interner::new();
-    let ref mut env = env::default(interner);
+    let ref env = env::default(interner);
 
     eval::eq("(+ 1 2)", "3", env, interner);
     eval::eq("(/ (- (+ 5 (* 2 3)) 3) 4)", "2", env, interner);
@@ -17,7 +18,7 @@ fn env() {
 #[test]
 fn def() {
     let ref mut interner = Interner::new();
-    let ref mut env = env::default(interner);
+    let ref env = env::default(interner);
 
     eval::eq("(def! x 3)", "3", env, interner);
     eval::eq("(def! x 4)", "4", env, interner);
@@ -29,7 +30,7 @@ fn def() {
 #[test]
 fn let_() {
     let ref mut interner = Interner::new();
-    let ref mut env = env::default(interner);
+    let ref env = env::default(interner);
 
     // from previous test
     eval::eq("(def! x 4)", "4", env, interner);
@@ -44,7 +45,7 @@ fn let_() {
 #[test]
 fn outer() {
     let ref mut interner = Interner::new();
-    let ref mut env = env::default(interner);
+    let ref env = env::default(interner);
 
     eval::eq("(def! a 4)", "4", env, interner);
     eval::eq("(let* (q 9) q)", "9", env, interner);
@@ -55,9 +56,23 @@ fn outer() {
 #[test]
 fn vector() {
     let ref mut interner = Interner::new();
-    let ref mut env = env::default(interner);
+    let ref env = env::default(interner);
 
     eval::eq("(let* [z 9] z)", "9", env, interner);
     eval::eq("(let* [p (+ 2 3) q (+ 2 p)] (+ p q))", "12", env, interner);
     eval::eq("(let* (a 5 b 6) [3 4 a [b 7] 8])", "[3 4 5 [6 7] 8]", env, interner);
 }
+
+#[test]
+fn shared() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+    let ref child = env.push();
+
+    child.insert(interner.intern("x"), Value::Integer(3));
+    eval::eq("x", "3", child, interner);
+    eval::err("x", eval::Error_::UndefinedSymbol, env, interner);
+
+    eval::eq("(def! y 5)", "5", env, interner);
+    eval::eq("(+ x y)", "8", child, interner);
+}
@@ -0,0 +1,188 @@
+extern crate lisp;
+
+mod eval;
+
+use std::collections::HashSet;
+
+use lisp::eval::{Arity, Type, env};
+use lisp::util::interner::Interner;
+
+#[test]
+fn fn_() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("((fn* [x] (* x x)) 3)", "9", env, interner);
+    eval::eq("((fn* (a b) (+ b 1)) 3 4)", "5", env, interner);
+    eval::eq("((fn* [] 4))", "4", env, interner);
+    eval::eq("((fn* [f x] (f x)) (fn* [a] (+ 1 a)) 7)", "8", env, interner);
+}
+
+#[test]
+fn def() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::ok("(def! square (fn* [x] (* x x)))", env, interner);
+    eval::eq("(square 5)", "25", env, interner);
+    eval::ok("(def! sumdown (fn* [n] (if (> n 0) (+ n (sumdown (- n 1))) 0)))", env, interner);
+    eval::eq("(sumdown 6)", "21", env, interner);
+}
+
+#[test]
+fn closures() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("(((fn* [a] (fn* [b] (+ a b))) 5) 7)", "12", env, interner);
+    eval::ok("(def! gen-plus5 (fn* [] (fn* [b] (+ 5 b))))", env, interner);
+    eval::ok("(def! plus5 (gen-plus5))", env, interner);
+    eval::eq("(plus5 7)", "12", env, interner);
+    eval::ok("(def! gen-plusX (fn* [x] (fn* [b] (+ x b))))", env, interner);
+    eval::ok("(def! plus7 (gen-plusX 7))", env, interner);
+    eval::eq("(plus7 8)", "15", env, interner);
+    eval::eq("(let* [x 2] ((fn* [] x)))", "2", env, interner);
+}
+
+#[test]
+fn err() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::err("(1 2 3)", eval::Error_::ExpectedFunction, env, interner);
+    let error = eval::Error_::ArityMismatch(Arity::Exactly(1), 0);
+    eval::err("((fn* [x] x))", error, env, interner);
+    let error = eval::Error_::ArityMismatch(Arity::Exactly(0), 2);
+    eval::err("(+ 1 ((fn* [] 2) 3 4))", error, env, interner);
+    eval::err("(fn* [1] x)", eval::Error_::ExpectedSymbol, env, interner);
+}
+
+#[test]
+fn env() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("(def! a 1)", "1", env, interner);
+    eval::ok("(def! f (fn* [] a))", env, interner);
+    eval::eq("(def! a 2)", "2", env, interner);
+    eval::eq("(f)", "2", env, interner);
+    eval::eq("((fn* [] (def! b 3)))", "3", env, interner);
+    eval::err("b", eval::Error_::UndefinedSymbol, env, interner);
+}
+
+#[test]
+fn if_() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("(if true 7 8)", "7", env, interner);
+    eval::eq("(if false 7 8)", "8", env, interner);
+    eval::eq("(if nil 7 8)", "8", env, interner);
+    eval::eq("(if 0 7 8)", "7", env, interner);
+    eval::eq("(if true (+ 1 7) (+ 1 8))", "8", env, interner);
+    eval::eq("(if false (+ 1 7))", "nil", env, interner);
+    eval::eq("(if nil 8)", "nil", env, interner);
+    eval::eq("(if true (+ 1 7))", "8", env, interner);
+}
+
+#[test]
+fn do_() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("(do)", "nil", env, interner);
+    eval::eq("(do (def! a 6) 7 (+ a 8))", "14", env, interner);
+    eval::eq("a", "6", env, interner);
+    eval::eq("(do (def! a 7) (def! b (+ a 1)) b)", "8", env, interner);
+}
+
+#[test]
+fn strings() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq(r#""""#, r#""""#, env, interner);
+    eval::eq(r#""abc""#, r#""abc""#, env, interner);
+    eval::eq(r#"(do "a\"b")"#, r#""a\"b""#, env, interner);
+    eval::eq(r#""a\tb\\c""#, r#""a\tb\\c""#, env, interner);
+    eval::eq(r#""\u{41}""#, r#""A""#, env, interner);
+    eval::ok(r#"(def! greet (fn* [] "hello"))"#, env, interner);
+    eval::eq("(greet)", r#""hello""#, env, interner);
+}
+
+#[test]
+fn lists() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("(list)", "()", env, interner);
+    eval::eq("(list 1 (list 2 3) [4])", "(1 (2 3) [4])", env, interner);
+    eval::eq("(list? (list))", "true", env, interner);
+    eval::eq("(list? [1 2])", "false", env, interner);
+    eval::eq("(empty? (list))", "true", env, interner);
+    eval::eq("(empty? [1])", "false", env, interner);
+    eval::eq("(count (list 1 2 3))", "3", env, interner);
+    eval::eq("(count [1 2])", "2", env, interner);
+    eval::eq("(count nil)", "0", env, interner);
+}
+
+#[test]
+fn sequences() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("(cons 1 (list 2 3))", "(1 2 3)", env, interner);
+    eval::eq("(cons [1] [2 3])", "([1] 2 3)", env, interner);
+    eval::eq("(concat)", "()", env, interner);
+    eval::eq("(concat (list 1 2) [3] nil (list 4))", "(1 2 3 4)", env, interner);
+    eval::eq("(first (list 1 2))", "1", env, interner);
+    eval::eq("(first [])", "nil", env, interner);
+    eval::eq("(first nil)", "nil", env, interner);
+    eval::eq("(rest [1 2 3])", "(2 3)", env, interner);
+    eval::eq("(rest (list))", "()", env, interner);
+    eval::eq("(rest nil)", "()", env, interner);
+    eval::eq("(nth [1 2 3] 2)", "3", env, interner);
+    eval::eq("(nth (list 1 2 3) 0)", "1", env, interner);
+    eval::eq("(conj (list 1 2) 3 4)", "(4 3 1 2)", env, interner);
+    eval::eq("(conj [1 2] 3 4)", "[1 2 3 4]", env, interner);
+
+    eval::err("(nth [1 2] 2)", eval::Error_::IndexOutOfBounds, env, interner);
+    eval::err("(nth [1 2] (- 0 1))", eval::Error_::IndexOutOfBounds, env, interner);
+    eval::span("(nth [1 2] (- 0 1))", "(- 0 1)", env, interner);
+
+    let error = eval::Error_::TypeMismatch(Type::Sequence, Type::Integer);
+    eval::err("(first 1)", error, env, interner);
+    eval::span("(cons 1 2)", "2", env, interner);
+    eval::span("(concat [1] nil 3)", "3", env, interner);
+}
+
+#[test]
+fn equality() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    assert_eq!(eval::value("(+ 1 2)", env, interner), eval::value("3", env, interner));
+    assert_eq!(eval::value("[1 (list 2)]", env, interner), eval::value("'(1 [2])", env, interner));
+    assert_eq!(eval::value("{:a [1]}", env, interner), eval::value("{:a '(1)}", env, interner));
+    assert!(eval::value("[1 2]", env, interner) != eval::value("[2 1]", env, interner));
+    assert!(eval::value("[]", env, interner) != eval::value("nil", env, interner));
+    assert!(eval::value("1", env, interner) != eval::value(r#""1""#, env, interner));
+    assert!(eval::value(":a", env, interner) != eval::value("'a", env, interner));
+
+    eval::eq("(= [1 2] (list 1 2))", "true", env, interner);
+    eval::eq("(= {[1] :a} {(list 1) :a})", "true", env, interner);
+    eval::eq("(= + +)", "true", env, interner);
+    eval::eq("(= + -)", "false", env, interner);
+    eval::eq("(= (fn* [] 1) (fn* [] 1))", "false", env, interner);
+    eval::eq("(let* [f (fn* [] 1)] (= f f))", "true", env, interner);
+    eval::eq("(= (atom 1) (atom 1))", "false", env, interner);
+    eval::eq("(let* [a (atom 1)] (= a a))", "true", env, interner);
+    eval::eq("(get {[1 2] :a} (list 1 2))", ":a", env, interner);
+
+    let mut set = HashSet::new();
+
+    set.insert(eval::value("[1 {:a 2 :b 3}]", env, interner));
+
+    assert!(set.contains(&eval::value("(list 1 {:b 3 :a 2})", env, interner)));
+    assert!(!set.contains(&eval::value("(list 1 {:b 3 :a 1})", env, interner)));
+}
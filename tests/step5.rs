@@ -0,0 +1,45 @@
+extern crate lisp;
+
+mod eval;
+
+use lisp::eval::env;
+use lisp::util::interner::Interner;
+
+#[test]
+fn if_() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::ok("(def! sum2 (fn* [n acc] (if (> n 0) (sum2 (- n 1) (+ n acc)) acc)))", env, interner);
+    eval::eq("(sum2 10 0)", "55", env, interner);
+    eval::eq("(sum2 1000000 0)", "500000500000", env, interner);
+}
+
+#[test]
+fn let_() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::ok("(def! down (fn* [n] (let* [m (- n 1)] (if (> m 0) (down m) m))))", env, interner);
+    eval::eq("(down 1000000)", "0", env, interner);
+}
+
+#[test]
+fn mutual() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::ok("(def! foo (fn* [n] (if (> n 0) (bar (- n 1)) :foo)))", env, interner);
+    eval::ok("(def! bar (fn* [n] (if (> n 0) (foo (- n 1)) :bar)))", env, interner);
+    eval::eq("(foo 1000000)", ":foo", env, interner);
+    eval::eq("(foo 1000001)", ":bar", env, interner);
+}
+
+#[test]
+fn do_() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::ok("(def! down (fn* [n] (do 1 (if (> n 0) (down (- n 1)) :done))))", env, interner);
+    eval::eq("(down 1000000)", ":done", env, interner);
+}
@@ -0,0 +1,174 @@
+extern crate lisp;
+
+mod eval;
+
+use std::io;
+
+use lisp::diagnostics;
+use lisp::eval::{Arity, Type, env};
+use lisp::syntax::codemap::SourceMap;
+use lisp::syntax::{parse, self};
+use lisp::util::interner::Interner;
+
+#[test]
+fn atoms() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("(atom 1)", "(atom 1)", env, interner);
+    eval::ok("(def! a (atom 2))", env, interner);
+    eval::eq("(atom? a)", "true", env, interner);
+    eval::eq("(atom? 1)", "false", env, interner);
+    eval::eq("(deref a)", "2", env, interner);
+    eval::eq("@a", "2", env, interner);
+    eval::eq("(reset! a 3)", "3", env, interner);
+    eval::eq("@a", "3", env, interner);
+    eval::eq("(swap! a (fn* [x] (* x 2)))", "6", env, interner);
+    eval::eq("(swap! a + 4)", "10", env, interner);
+    eval::eq("@a", "10", env, interner);
+    eval::eq("(swap! a (fn* [x y z] (list x y z)) 1 2)", "(10 1 2)", env, interner);
+    eval::eq("(swap! a (fn* [x] 1))", "1", env, interner);
+    eval::eq("(let* [f swap!] (f a + 1))", "2", env, interner);
+}
+
+#[test]
+fn cycle() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::ok("(def! a (atom nil))", env, interner);
+    eval::eq("(reset! a [a])", "[(atom [(atom ...)])]", env, interner);
+    eval::eq("a", "(atom [(atom ...)])", env, interner);
+    eval::eq("[a a]", "[(atom [(atom ...)]) (atom [(atom ...)])]", env, interner);
+}
+
+#[test]
+fn shared() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::ok("(def! counter (atom 0))", env, interner);
+    eval::ok("(def! inc! (fn* [] (swap! counter + 1)))", env, interner);
+    eval::ok("(do (inc!) (inc!) (inc!))", env, interner);
+    eval::eq("@counter", "3", env, interner);
+    eval::ok("(def! b counter)", env, interner);
+    eval::ok("(reset! b 0)", env, interner);
+    eval::eq("@counter", "0", env, interner);
+    eval::eq("(get {counter :counter} b)", ":counter", env, interner);
+    eval::eq("(get {counter :counter} (atom 0))", "nil", env, interner);
+}
+
+#[test]
+fn read_string() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq(r#"(read-string "(1 2 (3 4) nil)")"#, "(1 2 (3 4) nil)", env, interner);
+    eval::eq(r#"(read-string "(+ 2 3)")"#, "(+ 2 3)", env, interner);
+    eval::eq(r#"(read-string "7 ;; comment")"#, "7", env, interner);
+    eval::eq(r#"(read-string "(if true 1)")"#, "(if true 1)", env, interner);
+    eval::eq(r#"(read-string "@a")"#, "(deref a)", env, interner);
+    eval::eq(r#"(first (read-string "(def! a 1)"))"#, "def!", env, interner);
+    eval::eq(r#"(read-string "1 2")"#, "1", env, interner);
+    eval::eq(r#"(read-string "")"#, "nil", env, interner);
+    eval::eq(r#"(read-string ";c")"#, "nil", env, interner);
+}
+
+#[test]
+fn eval_() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("(eval '(+ 2 3))", "5", env, interner);
+    eval::eq("(eval (list + 1 2))", "3", env, interner);
+    eval::eq(r#"(eval (read-string "(+ 2 3)"))"#, "5", env, interner);
+    eval::eq("(eval (list '+ 1 2))", "3", env, interner);
+    eval::eq(r#"(eval (read-string "(if false 1 2)"))"#, "2", env, interner);
+    eval::ok("(def! a 1)", env, interner);
+    eval::eq("(let* [a 2] (eval 'a))", "1", env, interner);
+    eval::eq("(let* [b 2] (eval '(def! b 3)))", "3", env, interner);
+    eval::eq("b", "3", env, interner);
+}
+
+#[test]
+fn load_file() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq(r#"(load-file "tests/mal/inc.mal")"#, "nil", env, interner);
+    eval::eq("(inc1 7)", "8", env, interner);
+    eval::eq("(inc2 7)", "9", env, interner);
+    eval::eq(r#"(let* [x 1] (load-file "tests/mal/inc.mal"))"#, "nil", env, interner);
+}
+
+#[test]
+fn err() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    let error = eval::Error_::TypeMismatch(Type::Atom, Type::Integer);
+    eval::err("(deref 1)", error, env, interner);
+
+    let error = eval::Error_::TypeMismatch(Type::Atom, Type::Integer);
+    eval::err("(reset! 1 2)", error, env, interner);
+
+    let error = eval::Error_::TypeMismatch(Type::Atom, Type::Integer);
+    eval::err("(swap! 1 +)", error, env, interner);
+    eval::span("(swap! 1 +)", "1", env, interner);
+
+    eval::err("(swap! (atom 1) 2)", eval::Error_::ExpectedFunction, env, interner);
+    eval::span("(swap! (atom 1) 2)", "2", env, interner);
+
+    let error = eval::Error_::ArityMismatch(Arity::AtLeast(2), 1);
+    eval::err("(swap! (atom 1))", error, env, interner);
+
+    let error = eval::Error_::ArityMismatch(Arity::Exactly(0), 1);
+    eval::err("(swap! (atom 1) (fn* [] 2))", error, env, interner);
+
+    let error = eval::Error_::TypeMismatch(Type::Number, Type::Keyword);
+    eval::err("(swap! (atom :a) + 1)", error, env, interner);
+    eval::span("(swap! (atom :a) + 1)", "(atom :a)", env, interner);
+    eval::span("(swap! (atom 1) + 2 :b)", ":b", env, interner);
+
+    let error = eval::Error_::TypeMismatch(Type::String, Type::Integer);
+    eval::err("(read-string 1)", error, env, interner);
+
+    let error = eval::Error_::Syntax(syntax::Error_::UnclosedDelimiter);
+    eval::err(r#"(read-string "(+ 1")"#, error, env, interner);
+
+    let error = eval::Error_::ArityMismatch(Arity::Exactly(2), 1);
+    eval::err("(eval '(nth [1]))", error, env, interner);
+    eval::span("(eval '(nth [1]))", "(nth [1])", env, interner);
+
+    let error = eval::Error_::ArityMismatch(Arity::Exactly(1), 0);
+    eval::err("(eval)", error, env, interner);
+
+    let error = eval::Error_::Io(io::ErrorKind::NotFound);
+    eval::err(r#"(load-file "tests/mal/missing.mal")"#, error, env, interner);
+
+    let input = r#"(try* (load-file "tests/mal/error.mal") (catch* e e))"#;
+    eval::eq(input, r#""undefined symbol""#, env, interner);
+    eval::eq("x", "1", env, interner);
+}
+
+#[test]
+fn diagnostic() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+    let mut map = SourceMap::new();
+
+    let input = r#"(load-file "tests/mal/error.mal")"#;
+    let file = map.add(String::from("<repl>"), String::from(input));
+    let expr = parse::expr(map.source(file), interner).unwrap();
+    let error = lisp::eval::expr(&expr, env, &mut map, interner).unwrap_err();
+
+    // NB the loaded file gets added to the source map
+    let expected = "\
+error: undefined symbol
+ --> tests/mal/error.mal:4:2
+  |
+4 | (undefined-function x)
+  |  ^~~~~~~~~~~~~~~~~~
+";
+    assert_eq!(diagnostics::eval(error, &map, interner), expected);
+}
@@ -0,0 +1,51 @@
+extern crate lisp;
+
+mod eval;
+
+use lisp::eval::env;
+use lisp::util::interner::Interner;
+
+#[test]
+fn quote() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("(quote 7)", "7", env, interner);
+    eval::eq("(quote (1 2 3))", "(1 2 3)", env, interner);
+    eval::eq("(quote (1 2 (3 4)))", "(1 2 (3 4))", env, interner);
+    eval::eq("'a", "a", env, interner);
+    eval::eq("'(+ a [b :c \"d\"])", "(+ a [b :c \"d\"])", env, interner);
+    eval::eq("'(def! x (fn* [y] y))", "(def! x (fn* [y] y))", env, interner);
+    eval::eq("(list? '(1 2))", "true", env, interner);
+    eval::eq("(first ''a)", "quote", env, interner);
+}
+
+#[test]
+fn quasiquote() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("(quasiquote 7)", "7", env, interner);
+    eval::eq("`(1 2 3)", "(1 2 3)", env, interner);
+    eval::eq("(def! a 8)", "8", env, interner);
+    eval::eq("`a", "a", env, interner);
+    eval::eq("`~a", "8", env, interner);
+    eval::eq("`(1 a 3)", "(1 a 3)", env, interner);
+    eval::eq("`(1 ~a 3)", "(1 8 3)", env, interner);
+    eval::eq("`[1 ~a [~a]]", "[1 8 [8]]", env, interner);
+    eval::eq("(def! b '(1 \"b\" \"d\"))", "(1 \"b\" \"d\")", env, interner);
+    eval::eq("`(1 ~b 3)", "(1 (1 \"b\" \"d\") 3)", env, interner);
+    eval::eq("`(1 ~@b 3)", "(1 1 \"b\" \"d\" 3)", env, interner);
+    eval::eq("`(~@[1 2] ~@(list))", "(1 2)", env, interner);
+}
+
+#[test]
+fn err() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::err("(unquote 1)", eval::Error_::UnsupportedOperation, env, interner);
+    eval::err("~1", eval::Error_::UnsupportedOperation, env, interner);
+    eval::err("`(~@1)", eval::Error_::UnsupportedOperation, env, interner);
+    eval::err("(quote 1 2)", eval::Error_::UnsupportedOperation, env, interner);
+}
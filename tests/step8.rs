@@ -0,0 +1,64 @@
+extern crate lisp;
+
+mod eval;
+
+use lisp::eval::{Arity, env};
+use lisp::util::interner::Interner;
+
+#[test]
+fn defmacro() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::ok("(defmacro! one (fn* () 1))", env, interner);
+    eval::eq("(one)", "1", env, interner);
+    eval::ok("(defmacro! unless (fn* (pred a b) `(if ~pred ~b ~a)))", env, interner);
+    eval::eq("(unless false 7 8)", "7", env, interner);
+    eval::eq("(unless true 7 8)", "8", env, interner);
+    eval::ok("(defmacro! unless2 (fn* (pred a b) (list 'if pred b a)))", env, interner);
+    eval::eq("(unless2 false 7 8)", "7", env, interner);
+    eval::eq("(unless2 true 7 8)", "8", env, interner);
+    eval::eq("(let* [x 2] (unless (> x 1) :small :big))", ":big", env, interner);
+
+    // NB the expansion contains the `+` function itself, not the `+` symbol
+    eval::ok("(defmacro! add (fn* [a b] (list + a b)))", env, interner);
+    eval::eq("(let* [+ -] (add 1 2))", "3", env, interner);
+    eval::ok("(def! a (atom 1))", env, interner);
+    eval::ok("(defmacro! deref-a (fn* [] (list deref a)))", env, interner);
+    eval::eq("(deref-a)", "1", env, interner);
+}
+
+#[test]
+fn macroexpand() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::ok("(defmacro! unless (fn* (pred a b) (list 'if pred b a)))", env, interner);
+    eval::eq("(macroexpand-1 (unless 2 3 4))", "(if 2 4 3)", env, interner);
+    eval::eq("(macroexpand (unless 2 3 4))", "(if 2 4 3)", env, interner);
+    eval::eq("(macroexpand-1 (+ 1 2))", "(+ 1 2)", env, interner);
+    eval::eq("(macroexpand 7)", "7", env, interner);
+
+    eval::ok("(defmacro! identity (fn* [x] x))", env, interner);
+    eval::ok("(defmacro! twice (fn* [x] `(identity (identity ~x))))", env, interner);
+    eval::eq("(macroexpand-1 (twice 1))", "(identity (identity 1))", env, interner);
+    eval::eq("(macroexpand (twice 1))", "1", env, interner);
+    eval::eq("(twice (+ 1 2))", "3", env, interner);
+}
+
+#[test]
+fn err() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::ok("(defmacro! bad (fn* [] 'undefined))", env, interner);
+    eval::ok("(defmacro! unless (fn* (pred a b) `(if ~pred ~b ~a)))", env, interner);
+
+    let undefined = eval::Error_::MacroExpansion(Box::new(eval::Error_::UndefinedSymbol));
+    let mismatch = eval::Error_::ArityMismatch(Arity::Exactly(3), 1);
+    let mismatch = eval::Error_::MacroExpansion(Box::new(mismatch));
+
+    eval::err("(bad)", undefined, env, interner);
+    eval::err("(unless true)", mismatch, env, interner);
+    eval::err("(defmacro! m 1)", eval::Error_::ExpectedFunction, env, interner);
+}
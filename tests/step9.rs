@@ -0,0 +1,93 @@
+extern crate lisp;
+
+mod eval;
+
+use lisp::eval::env;
+use lisp::eval::{Arity, Type, Value};
+use lisp::util::interner::Interner;
+
+#[test]
+fn literals() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("{}", "{}", env, interner);
+    eval::eq("{:a (+ 1 2)}", "{:a 3}", env, interner);
+    eval::eq(r#"{"a" {:b [1 2]}}"#, r#"{"a" {:b [1 2]}}"#, env, interner);
+    eval::eq("(let* [m {:a 1 :b 2}] (get m :b))", "2", env, interner);
+
+    // NB entries are printed sorted by their keys
+    eval::eq("{:b 2 :a 1 :c 3}", "{:a 1 :b 2 :c 3}", env, interner);
+    let expected = r#"{"b" [1] 2 nil :a {:c 3 :d 4}}"#;
+    eval::eq(r#"{:a {:d 4 :c 3} 2 nil "b" [1]}"#, expected, env, interner);
+}
+
+#[test]
+fn hash_map() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("(hash-map)", "{}", env, interner);
+    eval::eq(r#"(hash-map "a" 1)"#, r#"{"a" 1}"#, env, interner);
+    eval::ok("(def! m (hash-map :a 1 :b 2))", env, interner);
+    eval::eq("(get m :a)", "1", env, interner);
+    eval::eq("(get m :c)", "nil", env, interner);
+    eval::eq("(get nil :a)", "nil", env, interner);
+    eval::eq("(contains? m :b)", "true", env, interner);
+    eval::eq("(contains? m :c)", "false", env, interner);
+    eval::eq("(get (assoc m :c 3) :c)", "3", env, interner);
+    eval::eq("(contains? m :c)", "false", env, interner);
+    eval::eq("(dissoc m :a :b)", "{}", env, interner);
+    eval::eq("(keys (dissoc m :a))", "(:b)", env, interner);
+    eval::eq("(vals (dissoc m :b))", "(1)", env, interner);
+    eval::eq("(keys {:c 3 :a 1 :b 2})", "(:a :b :c)", env, interner);
+    eval::eq("(vals {:c 3 :a 1 :b 2})", "(1 2 3)", env, interner);
+    eval::eq("(assoc m :c 3)", "{:a 1 :b 2 :c 3}", env, interner);
+    eval::eq("(get {[1 2] :vector {:k 1} :map} {:k 1})", ":map", env, interner);
+    eval::eq("(get {+ :plus} +)", ":plus", env, interner);
+    eval::eq("(get {+ :plus} -)", "nil", env, interner);
+}
+
+#[test]
+fn try_() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::eq("(try* 123 (catch* e 456))", "123", env, interner);
+    eval::eq("(try* (throw 123) (catch* e (+ e 1)))", "124", env, interner);
+    eval::eq("(try* (throw {:a 1}) (catch* e (get e :a)))", "1", env, interner);
+    eval::eq("(try* (nth [] 0) (catch* e e))", r#""index out of bounds""#, env, interner);
+    eval::eq("(try* (foo) (catch* e e))", r#""undefined symbol""#, env, interner);
+    eval::eq("(try* (do (throw :a) 1) (catch* e [e e]))", "[:a :a]", env, interner);
+    eval::ok("(def! f (fn* [x] (if (< x 0) (throw x) x)))", env, interner);
+    eval::eq("(try* (f (- 0 1)) (catch* e (- 0 e)))", "1", env, interner);
+    eval::eq("(try* (f 1) (catch* e (- 0 e)))", "1", env, interner);
+    eval::ok("(def! g (fn* [] (try* (throw 1) (catch* e (throw (+ e 1))))))", env, interner);
+    eval::eq("(try* (g) (catch* e e))", "2", env, interner);
+    eval::eq("(try* 1)", "1", env, interner);
+    eval::eq("(let* [e 1] (do (try* (throw 2) (catch* e e)) e))", "1", env, interner);
+    eval::eq("(let* [t throw] (try* (t :a) (catch* e e)))", ":a", env, interner);
+    eval::eq("(try* ((first [throw]) 1) (catch* e e))", "1", env, interner);
+}
+
+#[test]
+fn err() {
+    let ref mut interner = Interner::new();
+    let ref env = env::default(interner);
+
+    eval::err("(assoc {} :a)", eval::Error_::ExpectedMapValue, env, interner);
+    eval::err("(hash-map :a)", eval::Error_::ExpectedMapValue, env, interner);
+    eval::span("(hash-map :a 1 :b)", ":b", env, interner);
+
+    let error = eval::Error_::TypeMismatch(Type::Map, Type::Vector);
+    eval::err("(get [1] 0)", error, env, interner);
+
+    eval::err("(throw 1)", eval::Error_::Throw(Value::Integer(1)), env, interner);
+    eval::err("(try* (throw 1))", eval::Error_::Throw(Value::Integer(1)), env, interner);
+    eval::span("((fn* [x] (throw x)) 1)", "(throw x)", env, interner);
+    eval::span("(let* [f (fn* [] (do 1 (throw 2)))] (+ (f) 3))", "(throw 2)", env, interner);
+    eval::err("(throw)", eval::Error_::ArityMismatch(Arity::Exactly(1), 0), env, interner);
+    eval::err("(try* 1 (catch* 2 3))", eval::Error_::ExpectedSymbol, env, interner);
+    eval::err("(try* 1 (foo e 3))", eval::Error_::UnsupportedOperation, env, interner);
+    eval::err("(catch* e 1)", eval::Error_::UnsupportedOperation, env, interner);
+}